@@ -107,13 +107,34 @@ impl FileSystem {
         Ok(File(raw_file))
     }
 
-    pub fn read_file_as_string(&self, path: &str) -> Result<String, Error> {
+    /// Reads the whole file at `path`, looking in the data folder first and then the game's
+    /// pdx.
+    pub fn read_file(&self, path: &str) -> Result<Vec<u8>, Error> {
         let stat = self.stat(path)?;
         let mut buffer = alloc::vec![0; stat.size as usize];
         let sd_file = self.open(path, FileOptions::kFileRead | FileOptions::kFileReadData)?;
         sd_file.read(&mut buffer)?;
+        Ok(buffer)
+    }
+
+    pub fn read_file_as_string(&self, path: &str) -> Result<String, Error> {
+        let buffer = self.read_file(path)?;
         String::from_utf8(buffer).map_err(Error::msg)
     }
+
+    /// Creates or replaces the file at `path` in the data folder with the given contents.
+    pub fn write_file(&self, path: &str, contents: &[u8]) -> Result<(), Error> {
+        let sd_file = self.open(path, FileOptions::kFileWrite)?;
+        let written = sd_file.write(contents)?;
+        ensure!(
+            written == contents.len(),
+            "Short write to {}: {} of {} bytes",
+            path,
+            written,
+            contents.len()
+        );
+        sd_file.flush()
+    }
 }
 
 static mut FILE_SYSTEM: FileSystem = FileSystem(ptr::null_mut());
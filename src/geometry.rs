@@ -9,3 +9,21 @@ pub type GrPoint = euclid::default::Point2D<GrCoord>;
 pub type GrVector = euclid::default::Vector2D<GrCoord>;
 pub type GrRect = euclid::default::Rect<GrCoord>;
 pub type GrSize = euclid::default::Size2D<GrCoord>;
+
+/// Points that can be passed to drawing functions that take whole pixels.
+pub trait ToScreenPoint {
+    fn to_screen_point(&self) -> ScreenPoint;
+}
+
+impl ToScreenPoint for ScreenPoint {
+    fn to_screen_point(&self) -> ScreenPoint {
+        *self
+    }
+}
+
+impl ToScreenPoint for GrPoint {
+    /// Rounds to the nearest pixel.
+    fn to_screen_point(&self) -> ScreenPoint {
+        ScreenPoint::new(libm::roundf(self.x) as i32, libm::roundf(self.y) as i32)
+    }
+}
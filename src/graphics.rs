@@ -1,21 +1,49 @@
 use {
     crate::{
-        geometry::{ScreenPoint, ScreenRect, ScreenSize, ScreenVector},
+        geometry::{ScreenPoint, ScreenRect, ScreenSize, ScreenVector, ToScreenPoint},
         log_to_console, pd_func_caller, pd_func_caller_log,
         system::System,
     },
-    alloc::{format, rc::Rc, vec::Vec},
+    alloc::{format, rc::Rc, string::String, vec::Vec},
     anyhow::{anyhow, ensure, Error},
-    core::{cell::RefCell, ops::RangeInclusive, ptr, slice},
-    crankstart_sys::{ctypes::c_int, LCDBitmapTable, LCDPattern},
+    core::{
+        cell::RefCell,
+        ops::{Range, RangeInclusive},
+        ptr, slice,
+    },
+    crankstart_sys::{ctypes::c_int, LCDBitmapTable},
     cstr_core::{CStr, CString},
     euclid::default::{Point2D, Vector2D},
     hashbrown::HashMap,
 };
 
+pub mod camera;
+pub use camera::Camera;
+pub mod capture;
+pub use capture::FrameRecorder;
+pub mod decode;
+pub mod dither;
+pub use dither::Dither;
+pub mod font;
+pub use font::{Font, FontFamily, FontVariant};
+pub mod nine_slice;
+pub use nine_slice::{NineSlice, SliceFill};
+pub mod particles;
+pub use particles::{Emitter, Particle, ParticleStyle, ParticleSystem};
+pub mod pattern;
+pub use pattern::{AnimatedPattern, ScrollingPattern};
+pub mod shapes;
+pub use shapes::LineJoin;
+pub mod tilemap;
+pub use tilemap::{Tile, Tilemap};
+pub mod transition;
+pub use transition::{Transition, TransitionEffect};
+pub mod video;
+pub use video::{VideoInfo, VideoPlayer};
+
 pub use crankstart_sys::{
-    LCDBitmapDrawMode, LCDBitmapFlip, LCDLineCapStyle, LCDPolygonFillRule, LCDRect, LCDSolidColor,
-    PDRect, PDStringEncoding, LCD_COLUMNS, LCD_ROWS, LCD_ROWSIZE,
+    LCDBitmapDrawMode, LCDBitmapFlip, LCDLineCapStyle, LCDPattern, LCDPolygonFillRule, LCDRect,
+    LCDSolidColor, PDRect, PDStringEncoding, LCD_COLUMNS, LCD_ROWS, LCD_ROWSIZE,
 };
 
 pub fn rect_make(x: f32, y: f32, width: f32, height: f32) -> PDRect {
@@ -33,14 +61,20 @@ pub enum LCDColor {
     Pattern(LCDPattern),
 }
 
-impl From<LCDColor> for usize {
-    fn from(color: LCDColor) -> Self {
+impl LCDColor {
+    /// One of the 17 evenly spaced grey levels from `pattern::grey`, 0 black to 16 white.
+    pub fn grey(level: u8) -> Self {
+        LCDColor::Pattern(pattern::grey(level))
+    }
+}
+
+/// Converts to the value the C API expects.  For patterns this is a pointer into the `LCDColor`,
+/// so the conversion borrows it; the color must outlive any call the value is passed to.
+impl From<&LCDColor> for usize {
+    fn from(color: &LCDColor) -> Self {
         match color {
-            LCDColor::Solid(solid_color) => solid_color as usize,
-            LCDColor::Pattern(pattern) => {
-                let pattern_ptr = &pattern as *const u8;
-                pattern_ptr as usize
-            }
+            LCDColor::Solid(solid_color) => *solid_color as usize,
+            LCDColor::Pattern(pattern) => pattern.as_ptr() as usize,
         }
     }
 }
@@ -53,10 +87,55 @@ pub struct BitmapData {
     pub hasmask: bool,
 }
 
+/// Borrowed view of a bitmap's pixels.  Rows are `rowbytes` long, one bit per pixel, most
+/// significant bit first; a set bit is white in `data` and opaque in `mask`.
+#[derive(Debug)]
+pub(crate) struct BitmapPixels<'a> {
+    pub width: usize,
+    pub height: usize,
+    pub rowbytes: usize,
+    pub data: &'a [u8],
+    pub mask: Option<&'a [u8]>,
+}
+
+impl<'a> BitmapPixels<'a> {
+    pub fn row(&self, y: usize) -> &'a [u8] {
+        &self.data[y * self.rowbytes..(y + 1) * self.rowbytes]
+    }
+
+    pub fn mask_row(&self, y: usize) -> Option<&'a [u8]> {
+        self.mask
+            .map(|mask| &mask[y * self.rowbytes..(y + 1) * self.rowbytes])
+    }
+}
+
+/// Mutable view of a bitmap's pixels, laid out like `BitmapPixels`.
+#[derive(Debug)]
+pub(crate) struct BitmapPixelsMut<'a> {
+    pub width: usize,
+    pub height: usize,
+    pub rowbytes: usize,
+    pub data: &'a mut [u8],
+    pub mask: Option<&'a mut [u8]>,
+}
+
+struct RawPixels {
+    width: usize,
+    height: usize,
+    rowbytes: usize,
+    data: *mut u8,
+    mask: Option<*mut u8>,
+}
+
 #[derive(Debug)]
 pub struct BitmapInner {
     pub(crate) raw_bitmap: *mut crankstart_sys::LCDBitmap,
     owned: bool,
+    // Bitmaps fetched from a table live in the table's memory, so hold on to the table until
+    // they're dropped.
+    table: Option<Rc<RawBitmapTable>>,
+    // Masks share their bitmap's pixel data, so hold on to the bitmap until they're dropped.
+    parent: Option<Bitmap>,
 }
 
 impl BitmapInner {
@@ -82,6 +161,65 @@ impl BitmapInner {
         })
     }
 
+    pub(crate) fn get_pixels(&self) -> Result<BitmapPixels<'_>, Error> {
+        let pixels = self.get_raw_pixels()?;
+        let len = pixels.rowbytes * pixels.height;
+        Ok(BitmapPixels {
+            width: pixels.width,
+            height: pixels.height,
+            rowbytes: pixels.rowbytes,
+            data: unsafe { slice::from_raw_parts(pixels.data, len) },
+            mask: pixels
+                .mask
+                .map(|mask| unsafe { slice::from_raw_parts(mask as *const u8, len) }),
+        })
+    }
+
+    pub(crate) fn get_pixels_mut(&mut self) -> Result<BitmapPixelsMut<'_>, Error> {
+        let pixels = self.get_raw_pixels()?;
+        let len = pixels.rowbytes * pixels.height;
+        Ok(BitmapPixelsMut {
+            width: pixels.width,
+            height: pixels.height,
+            rowbytes: pixels.rowbytes,
+            data: unsafe { slice::from_raw_parts_mut(pixels.data, len) },
+            mask: pixels
+                .mask
+                .map(|mask| unsafe { slice::from_raw_parts_mut(mask, len) }),
+        })
+    }
+
+    /// Returns pointers to the bitmap's data and mask, so the callers can borrow them as
+    /// shared or mutable slices to match how they borrow the bitmap.
+    fn get_raw_pixels(&self) -> Result<RawPixels, Error> {
+        let mut width = 0;
+        let mut height = 0;
+        let mut rowbytes = 0;
+        let mut mask_ptr = ptr::null_mut();
+        let mut data_ptr = ptr::null_mut();
+        pd_func_caller!(
+            (*Graphics::get_ptr()).getBitmapData,
+            self.raw_bitmap,
+            &mut width,
+            &mut height,
+            &mut rowbytes,
+            &mut mask_ptr,
+            &mut data_ptr,
+        )?;
+        ensure!(!data_ptr.is_null(), "Null data returned from getBitmapData");
+        Ok(RawPixels {
+            width: width as usize,
+            height: height as usize,
+            rowbytes: rowbytes as usize,
+            data: data_ptr,
+            mask: if mask_ptr.is_null() {
+                None
+            } else {
+                Some(mask_ptr)
+            },
+        })
+    }
+
     pub fn draw(&self, location: ScreenPoint, flip: LCDBitmapFlip) -> Result<(), Error> {
         pd_func_caller!(
             (*Graphics::get_ptr()).drawBitmap,
@@ -137,6 +275,8 @@ impl BitmapInner {
         Ok(Self {
             raw_bitmap,
             owned: true,
+            table: None,
+            parent: None,
         })
     }
 
@@ -162,16 +302,19 @@ impl BitmapInner {
         pd_func_caller!(
             (*Graphics::get_ptr()).clearBitmap,
             self.raw_bitmap,
-            color.into()
+            usize::from(&color)
         )
     }
 
     pub fn duplicate(&self) -> Result<Self, Error> {
         let raw_bitmap = pd_func_caller!((*Graphics::get_ptr()).copyBitmap, self.raw_bitmap)?;
 
+        // copyBitmap always allocates a new bitmap, even if this one belongs to a table.
         Ok(Self {
             raw_bitmap,
-            owned: self.owned,
+            owned: true,
+            table: None,
+            parent: None,
         })
     }
 
@@ -188,19 +331,27 @@ impl BitmapInner {
         todo!();
     }
 
-    pub fn into_color(&self, bitmap: Bitmap, top_left: Point2D<i32>) -> Result<LCDColor, Error> {
-        let mut pattern = LCDPattern::default();
-        let pattern_ptr = pattern.as_mut_ptr();
-        let mut pattern_val = pattern_ptr as usize;
+    pub fn get_pattern(&self, top_left: Point2D<i32>) -> Result<LCDPattern, Error> {
+        let mut color: crankstart_sys::LCDColor = 0;
         let graphics = Graphics::get();
         pd_func_caller!(
             (*graphics.0).setColorToPattern,
-            &mut pattern_val,
+            &mut color,
             self.raw_bitmap,
             top_left.x,
             top_left.y
         )?;
-        Ok(LCDColor::Pattern(pattern))
+        ensure!(
+            color > LCDSolidColor::kColorXOR as usize,
+            "setColorToPattern did not return a pattern"
+        );
+        // The pattern lives in memory owned by the system, so copy it into the color rather than
+        // keeping a pointer to it.
+        Ok(unsafe { *(color as *const LCDPattern) })
+    }
+
+    pub fn into_color(&self, bitmap: Bitmap, top_left: Point2D<i32>) -> Result<LCDColor, Error> {
+        Ok(LCDColor::Pattern(self.get_pattern(top_left)?))
     }
 
     pub fn load(&self, path: &str) -> Result<(), Error> {
@@ -234,6 +385,10 @@ impl BitmapInner {
         }
     }
 
+    fn get_mask(&self) -> Result<*mut crankstart_sys::LCDBitmap, Error> {
+        pd_func_caller!((*Graphics::get_ptr()).getBitmapMask, self.raw_bitmap)
+    }
+
     pub fn check_mask_collision(
         &self,
         my_location: ScreenPoint,
@@ -280,7 +435,26 @@ pub struct Bitmap {
 impl Bitmap {
     fn new(raw_bitmap: *mut crankstart_sys::LCDBitmap, owned: bool) -> Self {
         Bitmap {
-            inner: Rc::new(RefCell::new(BitmapInner { raw_bitmap, owned })),
+            inner: Rc::new(RefCell::new(BitmapInner {
+                raw_bitmap,
+                owned,
+                table: None,
+                parent: None,
+            })),
+        }
+    }
+
+    fn new_from_table(
+        raw_bitmap: *mut crankstart_sys::LCDBitmap,
+        table: Rc<RawBitmapTable>,
+    ) -> Self {
+        Bitmap {
+            inner: Rc::new(RefCell::new(BitmapInner {
+                raw_bitmap,
+                owned: false,
+                table: Some(table),
+                parent: None,
+            })),
         }
     }
 
@@ -332,6 +506,14 @@ impl Bitmap {
         self.inner.borrow().clear(color)
     }
 
+    /// Returns a new bitmap with a copy of this one's contents.
+    pub fn duplicate(&self) -> Result<Bitmap, Error> {
+        let inner = self.inner.borrow().duplicate()?;
+        Ok(Self {
+            inner: Rc::new(RefCell::new(inner)),
+        })
+    }
+
     pub fn transform(&self, rotation: f32, scale: Vector2D<f32>) -> Result<Bitmap, Error> {
         let inner = self.inner.borrow().transform(rotation, scale)?;
         Ok(Self {
@@ -343,6 +525,12 @@ impl Bitmap {
         self.inner.borrow().into_color(bitmap, top_left)
     }
 
+    /// Returns the 8x8 pattern, including mask, of this bitmap with its top left corner at
+    /// `top_left`, for drawing with `LCDColor::Pattern`.
+    pub fn get_pattern(&self, top_left: Point2D<i32>) -> Result<LCDPattern, Error> {
+        self.inner.borrow().get_pattern(top_left)
+    }
+
     pub fn load(&self, path: &str) -> Result<(), Error> {
         self.inner.borrow().load(path)
     }
@@ -351,6 +539,23 @@ impl Bitmap {
         self.inner.borrow().set_bitmap_mask(mask)
     }
 
+    /// Returns the bitmap's mask, or None if it doesn't have one.  The mask shares this bitmap's
+    /// data, so drawing into it changes which pixels of this bitmap are transparent.
+    pub fn mask(&self) -> Result<Option<Bitmap>, Error> {
+        let raw_mask = self.inner.borrow().get_mask()?;
+        if raw_mask.is_null() {
+            return Ok(None);
+        }
+        Ok(Some(Bitmap {
+            inner: Rc::new(RefCell::new(BitmapInner {
+                raw_bitmap: raw_mask,
+                owned: true,
+                table: None,
+                parent: Some(self.clone()),
+            })),
+        }))
+    }
+
     pub fn check_mask_collision(
         &self,
         my_location: ScreenPoint,
@@ -381,25 +586,76 @@ fn raw_bitmap(bitmap: OptionalBitmap<'_>) -> *mut crankstart_sys::LCDBitmap {
     }
 }
 
-pub struct Font(*mut crankstart_sys::LCDFont);
+/// Size information for a `BitmapTable`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BitmapTableInfo {
+    /// Number of bitmaps in the table.
+    pub count: usize,
+    /// Number of cells in each row of the table's image, for tables loaded from a
+    /// `name-table-w-h.png` grid.
+    pub cells_wide: usize,
+}
 
-impl Font {
-    pub fn new(font: *mut crankstart_sys::LCDFont) -> Result<Self, Error> {
-        anyhow::ensure!(!font.is_null(), "Null pointer passed to Font::new");
-        Ok(Self(font))
+/// A named range of frames in a `BitmapTable`, played back at a fixed rate.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AnimationClip {
+    pub frames: Range<usize>,
+    pub frame_duration_ms: usize,
+    pub looping: bool,
+}
+
+impl AnimationClip {
+    pub fn new(frames: Range<usize>, frame_duration_ms: usize, looping: bool) -> Self {
+        Self {
+            frames,
+            frame_duration_ms,
+            looping,
+        }
+    }
+
+    /// Returns the total length of one pass through the clip, in milliseconds.
+    pub fn duration_ms(&self) -> usize {
+        self.frames.len() * self.frame_duration_ms
+    }
+
+    /// Returns whether a non-looping clip has played all of its frames after `elapsed_ms`.
+    pub fn is_finished(&self, elapsed_ms: usize) -> bool {
+        !self.looping && elapsed_ms >= self.duration_ms()
+    }
+
+    /// Returns the table index of the frame to show `elapsed_ms` after the clip started.
+    /// Non-looping clips hold their last frame once finished.
+    pub fn frame_at(&self, elapsed_ms: usize) -> usize {
+        let len = self.frames.len();
+        if len == 0 {
+            return self.frames.start;
+        }
+        let step = elapsed_ms / self.frame_duration_ms.max(1);
+        let offset = if self.looping {
+            step % len
+        } else {
+            step.min(len - 1)
+        };
+        self.frames.start + offset
     }
 }
 
-impl Drop for Font {
+/// Owns the underlying table; shared by the `BitmapTable` and any bitmaps fetched from it.
+#[derive(Debug)]
+struct RawBitmapTable(*mut LCDBitmapTable);
+
+impl Drop for RawBitmapTable {
     fn drop(&mut self) {
-        log_to_console!("Leaking a font");
+        pd_func_caller_log!((*Graphics::get_ptr()).freeBitmapTable, self.0);
     }
 }
 
 #[derive(Debug)]
 struct BitmapTableInner {
-    raw_bitmap_table: *mut LCDBitmapTable,
+    raw_bitmap_table: Rc<RawBitmapTable>,
+    caching: bool,
     bitmaps: HashMap<usize, Bitmap>,
+    clips: HashMap<String, AnimationClip>,
 }
 
 impl BitmapTableInner {
@@ -409,7 +665,7 @@ impl BitmapTableInner {
         } else {
             let raw_bitmap = pd_func_caller!(
                 (*Graphics::get_ptr()).getTableBitmap,
-                self.raw_bitmap_table,
+                self.raw_bitmap_table.0,
                 index as c_int
             )?;
             ensure!(
@@ -418,12 +674,29 @@ impl BitmapTableInner {
                 index,
                 self.raw_bitmap_table
             );
-            let bitmap = Bitmap::new(raw_bitmap, true);
-            self.bitmaps.insert(index, bitmap.clone());
+            let bitmap = Bitmap::new_from_table(raw_bitmap, self.raw_bitmap_table.clone());
+            if self.caching {
+                self.bitmaps.insert(index, bitmap.clone());
+            }
             Ok(bitmap)
         }
     }
 
+    fn get_info(&self) -> Result<BitmapTableInfo, Error> {
+        let mut count = 0;
+        let mut cells_wide = 0;
+        pd_func_caller!(
+            (*Graphics::get_ptr()).getBitmapTableInfo,
+            self.raw_bitmap_table.0,
+            &mut count,
+            &mut cells_wide
+        )?;
+        Ok(BitmapTableInfo {
+            count: count as usize,
+            cells_wide: cells_wide as usize,
+        })
+    }
+
     fn load(&mut self, path: &str) -> Result<(), Error> {
         let c_path = CString::new(path).map_err(Error::msg)?;
         let mut out_err: *const crankstart_sys::ctypes::c_char = ptr::null_mut();
@@ -431,9 +704,11 @@ impl BitmapTableInner {
         pd_func_caller!(
             (*graphics.0).loadIntoBitmapTable,
             c_path.as_ptr(),
-            self.raw_bitmap_table,
+            self.raw_bitmap_table.0,
             &mut out_err
         )?;
+        // The table's contents were replaced, so cached bitmaps are stale.
+        self.bitmaps.clear();
         if !out_err.is_null() {
             let err_msg = unsafe { CStr::from_ptr(out_err).to_string_lossy().into_owned() };
             Err(anyhow!(err_msg))
@@ -443,15 +718,6 @@ impl BitmapTableInner {
     }
 }
 
-impl Drop for BitmapTableInner {
-    fn drop(&mut self) {
-        pd_func_caller_log!(
-            (*Graphics::get_ptr()).freeBitmapTable,
-            self.raw_bitmap_table
-        );
-    }
-}
-
 type BitmapTableInnerPtr = Rc<RefCell<BitmapTableInner>>;
 
 #[derive(Clone, Debug)]
@@ -463,8 +729,10 @@ impl BitmapTable {
     pub fn new(raw_bitmap_table: *mut LCDBitmapTable) -> Self {
         Self {
             inner: Rc::new(RefCell::new(BitmapTableInner {
-                raw_bitmap_table,
+                raw_bitmap_table: Rc::new(RawBitmapTable(raw_bitmap_table)),
+                caching: true,
                 bitmaps: HashMap::new(),
+                clips: HashMap::new(),
             })),
         }
     }
@@ -476,10 +744,129 @@ impl BitmapTable {
     pub fn get_bitmap(&self, index: usize) -> Result<Bitmap, Error> {
         self.inner.borrow_mut().get_bitmap(index)
     }
+
+    /// Returns the number of bitmaps in the table and how many cells wide its image is.
+    pub fn get_info(&self) -> Result<BitmapTableInfo, Error> {
+        self.inner.borrow().get_info()
+    }
+
+    /// Returns the number of bitmaps in the table.
+    pub fn len(&self) -> Result<usize, Error> {
+        Ok(self.get_info()?.count)
+    }
+
+    /// Returns the bitmap at column `x` and row `y` of a table loaded from a grid image.
+    pub fn get_cell(&self, x: usize, y: usize) -> Result<Bitmap, Error> {
+        let info = self.get_info()?;
+        ensure!(
+            x < info.cells_wide,
+            "Column {} out of range for table {} cells wide",
+            x,
+            info.cells_wide
+        );
+        self.get_bitmap(y * info.cells_wide + x)
+    }
+
+    /// By default, every bitmap fetched from the table is kept so later fetches are cheap.
+    /// Turning caching off releases the cached bitmaps and makes each fetch return a fresh
+    /// `Bitmap`, which is freed as soon as the caller drops it.
+    pub fn set_caching(&self, caching: bool) {
+        let mut inner = self.inner.borrow_mut();
+        inner.caching = caching;
+        if !caching {
+            inner.bitmaps.clear();
+        }
+    }
+
+    /// Returns an iterator over every bitmap in the table, in index order.
+    pub fn frames(&self) -> Result<BitmapTableFrames, Error> {
+        let count = self.len()?;
+        Ok(self.frames_in(0..count))
+    }
+
+    /// Returns an iterator over the bitmaps in the given range of indexes.
+    pub fn frames_in(&self, range: Range<usize>) -> BitmapTableFrames {
+        BitmapTableFrames {
+            table: self.clone(),
+            range,
+        }
+    }
+
+    /// Declares a named animation over a range of the table's frames.  Replaces any clip
+    /// previously added with the same name.
+    pub fn add_clip(&self, name: &str, clip: AnimationClip) -> Result<(), Error> {
+        let count = self.len()?;
+        ensure!(
+            clip.frames.start <= clip.frames.end && clip.frames.end <= count,
+            "Clip {} frames {:?} out of range for table of {} bitmaps",
+            name,
+            clip.frames,
+            count
+        );
+        self.inner.borrow_mut().clips.insert(name.into(), clip);
+        Ok(())
+    }
+
+    pub fn get_clip(&self, name: &str) -> Option<AnimationClip> {
+        self.inner.borrow().clips.get(name).cloned()
+    }
+
+    /// Returns the bitmap to show for the named clip `elapsed_ms` after it started.
+    pub fn get_clip_bitmap(&self, name: &str, elapsed_ms: usize) -> Result<Bitmap, Error> {
+        let clip = self
+            .get_clip(name)
+            .ok_or_else(|| anyhow!("No clip named {} in table", name))?;
+        self.get_bitmap(clip.frame_at(elapsed_ms))
+    }
+
+    /// Returns an iterator over the frames of the named clip.
+    pub fn clip_frames(&self, name: &str) -> Result<BitmapTableFrames, Error> {
+        let clip = self
+            .get_clip(name)
+            .ok_or_else(|| anyhow!("No clip named {} in table", name))?;
+        Ok(self.frames_in(clip.frames))
+    }
+}
+
+/// Iterator over a range of bitmaps in a `BitmapTable`; see `BitmapTable::frames`.
+#[derive(Clone, Debug)]
+pub struct BitmapTableFrames {
+    table: BitmapTable,
+    range: Range<usize>,
+}
+
+impl Iterator for BitmapTableFrames {
+    type Item = Result<Bitmap, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.range.next().map(|index| self.table.get_bitmap(index))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.range.size_hint()
+    }
+}
+
+impl DoubleEndedIterator for BitmapTableFrames {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.range
+            .next_back()
+            .map(|index| self.table.get_bitmap(index))
+    }
 }
 
+impl ExactSizeIterator for BitmapTableFrames {}
+
 static mut GRAPHICS: Graphics = Graphics(ptr::null_mut());
 
+// The firmware keeps drawing with the current font after set_font returns, so hold a reference
+// to it here to keep it from being freed while it's still in use.
+static mut CURRENT_FONT: Option<Font> = None;
+
+// The system doesn't report the line cap style or draw offset, so remember the last ones set.
+static mut LINE_CAP_STYLE: LCDLineCapStyle = LCDLineCapStyle::kLineCapStyleButt;
+static mut DRAW_OFFSET: ScreenVector = ScreenVector::new(0, 0);
+
 #[derive(Clone, Debug)]
 pub struct Graphics(*const crankstart_sys::playdate_graphics);
 
@@ -550,15 +937,34 @@ impl Graphics {
         Ok(Bitmap::new(raw_bitmap, false))
     }
 
-    pub fn get_framebuffer_bitmap(&self) -> Result<Bitmap, Error> {
+    /// Returns a copy of the contents of the working framebuffer, i.e. what's been drawn so far
+    /// this frame.  The copy is independent of the framebuffer, so it's suitable for keeping
+    /// around, e.g. as the outgoing frame of a transition or a pause-screen snapshot.
+    pub fn copy_framebuffer(&self) -> Result<Bitmap, Error> {
         let raw_bitmap = pd_func_caller!((*self.0).copyFrameBufferBitmap)?;
         anyhow::ensure!(
             !raw_bitmap.is_null(),
-            "Null pointer returned from getFrameBufferBitmap"
+            "Null pointer returned from copyFrameBufferBitmap"
         );
         Ok(Bitmap::new(raw_bitmap, true))
     }
 
+    #[deprecated(note = "use copy_framebuffer")]
+    pub fn get_framebuffer_bitmap(&self) -> Result<Bitmap, Error> {
+        self.copy_framebuffer()
+    }
+
+    /// Returns a bitmap of the display buffer, i.e. the last frame sent to the screen.  The
+    /// system owns this bitmap and updates it every frame; use `duplicate` to keep a copy.
+    pub fn display_buffer_bitmap(&self) -> Result<Bitmap, Error> {
+        let raw_bitmap = pd_func_caller!((*self.0).getDisplayBufferBitmap)?;
+        anyhow::ensure!(
+            !raw_bitmap.is_null(),
+            "Null pointer returned from getDisplayBufferBitmap"
+        );
+        Ok(Bitmap::new(raw_bitmap, false))
+    }
+
     pub fn set_background_color(&self, color: LCDSolidColor) -> Result<(), Error> {
         pd_func_caller!((*self.0).setBackgroundColor, color)
     }
@@ -577,7 +983,16 @@ impl Graphics {
     }
 
     pub fn set_draw_offset(&self, offset: ScreenVector) -> Result<(), Error> {
-        pd_func_caller!((*self.0).setDrawOffset, offset.x, offset.y)
+        pd_func_caller!((*self.0).setDrawOffset, offset.x, offset.y)?;
+        unsafe {
+            DRAW_OFFSET = offset;
+        }
+        Ok(())
+    }
+
+    /// Returns the offset last passed to `set_draw_offset`.
+    pub fn get_draw_offset(&self) -> ScreenVector {
+        unsafe { DRAW_OFFSET }
     }
 
     pub fn new_bitmap(&self, size: ScreenSize, bg_color: LCDColor) -> Result<Bitmap, Error> {
@@ -585,7 +1000,7 @@ impl Graphics {
             (*self.0).newBitmap,
             size.width,
             size.height,
-            bg_color.into()
+            usize::from(&bg_color)
         )?;
         anyhow::ensure!(
             !raw_bitmap.is_null(),
@@ -643,7 +1058,7 @@ impl Graphics {
     }
 
     pub fn clear(&self, color: LCDColor) -> Result<(), Error> {
-        pd_func_caller!((*self.0).clear, color.into())
+        pd_func_caller!((*self.0).clear, usize::from(&color))
     }
 
     pub fn draw_line(
@@ -660,19 +1075,35 @@ impl Graphics {
             p2.x,
             p2.y,
             width,
-            color.into(),
+            usize::from(&color),
         )
     }
 
-    pub fn fill_polygon(
+    /// Sets the style of the ends of lines drawn with `draw_line`.
+    pub fn set_line_cap_style(&self, style: LCDLineCapStyle) -> Result<(), Error> {
+        pd_func_caller!((*self.0).setLineCapStyle, style)?;
+        unsafe {
+            LINE_CAP_STYLE = style;
+        }
+        Ok(())
+    }
+
+    pub fn get_line_cap_style(&self) -> LCDLineCapStyle {
+        unsafe { LINE_CAP_STYLE }
+    }
+
+    /// Fills a polygon with whole-pixel (`ScreenPoint`) or fractional (`GrPoint`) vertices;
+    /// fractional vertices are rounded to the nearest pixel.
+    pub fn fill_polygon<P: ToScreenPoint>(
         &self,
-        coords: &[ScreenPoint],
+        coords: &[P],
         color: LCDColor,
         fillrule: LCDPolygonFillRule,
     ) -> Result<(), Error> {
         let n_pts = coords.len();
         let mut coords_seq = coords
             .iter()
+            .map(ToScreenPoint::to_screen_point)
             .flat_map(|pt| [pt.x, pt.y])
             .collect::<alloc::vec::Vec<_>>();
 
@@ -680,7 +1111,7 @@ impl Graphics {
             (*self.0).fillPolygon,
             n_pts as i32,
             coords_seq.as_mut_ptr(),
-            color.into(),
+            usize::from(&color),
             fillrule
         )?;
 
@@ -702,7 +1133,7 @@ impl Graphics {
             p2.y,
             p3.x,
             p3.y,
-            color.into(),
+            usize::from(&color),
         )
     }
 
@@ -713,7 +1144,7 @@ impl Graphics {
             rect.origin.y,
             rect.size.width,
             rect.size.height,
-            color.into(),
+            usize::from(&color),
         )
     }
 
@@ -724,7 +1155,7 @@ impl Graphics {
             rect.origin.y,
             rect.size.width,
             rect.size.height,
-            color.into(),
+            usize::from(&color),
         )
     }
 
@@ -746,7 +1177,7 @@ impl Graphics {
             line_width,
             start_angle,
             end_angle,
-            color.into(),
+            usize::from(&color),
         )
     }
 
@@ -770,7 +1201,7 @@ impl Graphics {
             size.height,
             start_angle,
             end_angle,
-            color.into(),
+            usize::from(&color),
         )
     }
 
@@ -792,11 +1223,92 @@ impl Graphics {
         }
     }
 
+    /// Loads a font from the contents of an uncompressed `.pft` file, minus its 16-byte header,
+    /// for example `&include_bytes!("font.pft")[16..]`.  `wide` should match the header flag
+    /// indicating that the font has glyphs above U+1FFFF.  The data is copied, so it doesn't need
+    /// to outlive the `Font`.
+    pub fn make_font_from_data(&self, data: &[u8], wide: bool) -> Result<Font, Error> {
+        let mut data = data.to_vec();
+        let font = pd_func_caller!(
+            (*self.0).makeFontFromData,
+            data.as_mut_ptr() as *mut crankstart_sys::LCDFontData,
+            wide as c_int
+        )?;
+        ensure!(
+            !font.is_null(),
+            "Null pointer returned from makeFontFromData"
+        );
+        Font::new_with_data(font, data)
+    }
+
     pub fn set_font(&self, font: &Font) -> Result<(), Error> {
-        pd_func_caller_log!((*self.0).setFont, font.0);
+        pd_func_caller_log!((*self.0).setFont, font.raw_font());
+        unsafe {
+            CURRENT_FONT = Some(font.clone());
+        }
         Ok(())
     }
 
+    /// Loads the regular font and, if given, the bold and italic fonts of a `FontFamily`.
+    pub fn load_font_family(
+        &self,
+        regular_path: &str,
+        bold_path: Option<&str>,
+        italic_path: Option<&str>,
+    ) -> Result<FontFamily, Error> {
+        let mut family = FontFamily::new(self.load_font(regular_path)?);
+        if let Some(bold_path) = bold_path {
+            family = family.with_bold(self.load_font(bold_path)?);
+        }
+        if let Some(italic_path) = italic_path {
+            family = family.with_italic(self.load_font(italic_path)?);
+        }
+        Ok(family)
+    }
+
+    pub fn get_text_tracking(&self) -> Result<i32, Error> {
+        pd_func_caller!((*self.0).getTextTracking)
+    }
+
+    pub fn set_text_tracking(&self, tracking: i32) -> Result<(), Error> {
+        pd_func_caller!((*self.0).setTextTracking, tracking)
+    }
+
+    /// Draws text using the fonts of `family`, switching variants with `*bold*` and `_italic_`
+    /// markup.  Leaves the family's regular font set as the current font.  Returns the width of
+    /// the drawn text.
+    pub fn draw_styled_text(
+        &self,
+        family: &FontFamily,
+        text: &str,
+        position: ScreenPoint,
+    ) -> Result<i32, Error> {
+        let tracking = self.get_text_tracking()?;
+        let mut x = position.x;
+        for (variant, run) in font::styled_runs(text) {
+            let font = family.get(variant);
+            self.set_font(font)?;
+            self.draw_text(&run, ScreenPoint::new(x, position.y))?;
+            x += self.get_text_width(font, &run, tracking)?;
+        }
+        self.set_font(family.regular())?;
+        Ok(x - position.x)
+    }
+
+    /// Returns the width `draw_styled_text` would draw the given text with.
+    pub fn get_styled_text_width(
+        &self,
+        family: &FontFamily,
+        text: &str,
+        tracking: i32,
+    ) -> Result<i32, Error> {
+        let mut width = 0;
+        for (variant, run) in font::styled_runs(text) {
+            width += self.get_text_width(family.get(variant), &run, tracking)?;
+        }
+        Ok(width)
+    }
+
     pub fn draw_text(&self, text: &str, position: ScreenPoint) -> Result<i32, Error> {
         let c_text = CString::new(text).map_err(Error::msg)?;
         pd_func_caller!(
@@ -813,7 +1325,7 @@ impl Graphics {
         let c_text = CString::new(text).map_err(Error::msg)?;
         pd_func_caller!(
             (*self.0).getTextWidth,
-            font.0,
+            font.raw_font(),
             c_text.as_ptr() as *const core::ffi::c_void,
             text.len(),
             PDStringEncoding::kUTF8Encoding,
@@ -822,7 +1334,7 @@ impl Graphics {
     }
 
     pub fn get_font_height(&self, font: &Font) -> Result<u8, Error> {
-        pd_func_caller!((*self.0).getFontHeight, font.0)
+        pd_func_caller!((*self.0).getFontHeight, font.raw_font())
     }
 
     pub fn get_system_text_width(&self, text: &str, tracking: i32) -> Result<i32, Error> {
@@ -837,3 +1349,14 @@ impl Graphics {
         )
     }
 }
+
+/// Returns a number between -1.0 and 1.0 from an xorshift generator, for effects that need
+/// cheap randomness.  `state` must not be zero.
+pub(crate) fn next_random(state: &mut u32) -> f32 {
+    let mut x = *state;
+    x ^= x << 13;
+    x ^= x >> 17;
+    x ^= x << 5;
+    *state = x;
+    (x as f32 / u32::MAX as f32) * 2.0 - 1.0
+}
@@ -0,0 +1,300 @@
+//! A camera that scrolls the world by setting the draw offset.
+//!
+//! Call `Camera::update` once a frame before drawing.  The camera follows its target sprite,
+//! keeps within the world bounds, moves parallax sprites and shakes the display.
+//!
+//! ```ignore
+//! let mut camera = Camera::new()?;
+//! camera.follow(Some(player.clone()));
+//! camera.set_bounds(Some(rect(0.0, 0.0, 1600.0, 480.0)));
+//! camera.set_dead_zone(Some(rect(150.0, 80.0, 100.0, 80.0)));
+//! camera.add_hud_sprite(&score)?;
+//! ```
+
+use {
+    super::{next_random, Graphics},
+    crate::{
+        display::Display,
+        geometry::{GrPoint, GrRect, GrSize, GrVector, ScreenPoint, ScreenVector},
+        sprite::Sprite,
+        system::System,
+    },
+    alloc::vec::Vec,
+    anyhow::Error,
+};
+
+/// A sprite that scrolls at a different speed from the world, for depth.
+#[derive(Clone, Debug)]
+struct ParallaxSprite {
+    sprite: Sprite,
+    // Where the sprite's center is when the camera is at the world origin.
+    position: GrPoint,
+    // How far the sprite moves for each pixel the camera moves; 0.0 stays put on screen and
+    // 1.0 moves with the world.
+    factor: GrVector,
+}
+
+#[derive(Clone, Debug)]
+struct Shake {
+    magnitude: f32,
+    duration_ms: usize,
+    elapsed_ms: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct Camera {
+    // The world point at the top left of the screen.
+    position: GrPoint,
+    viewport: GrSize,
+    target: Option<Sprite>,
+    dead_zone: Option<GrRect>,
+    bounds: Option<GrRect>,
+    smoothing_ms: f32,
+    shake: Option<Shake>,
+    // State of the xorshift generator for shake offsets.
+    seed: u32,
+    parallax: Vec<ParallaxSprite>,
+}
+
+impl Camera {
+    /// Creates a camera at the world origin, the size of the display.
+    pub fn new() -> Result<Self, Error> {
+        let size = Display::get().get_size()?;
+        Ok(Self {
+            position: GrPoint::zero(),
+            viewport: GrSize::new(size.width as f32, size.height as f32),
+            target: None,
+            dead_zone: None,
+            bounds: None,
+            smoothing_ms: 0.0,
+            shake: None,
+            seed: 0x2545_f491,
+            parallax: Vec::new(),
+        })
+    }
+
+    /// Returns the world point at the top left of the screen.
+    pub fn get_position(&self) -> GrPoint {
+        self.position
+    }
+
+    /// Moves the camera so `position` is at the top left of the screen, immediately.
+    pub fn set_position(&mut self, position: GrPoint) {
+        self.position = self.clamp(position);
+    }
+
+    /// Moves the camera so `center` is in the middle of the screen, immediately.
+    pub fn center_on(&mut self, center: GrPoint) {
+        self.set_position(center - self.viewport.to_vector() / 2.0);
+    }
+
+    /// Returns the part of the world on screen.
+    pub fn visible_rect(&self) -> GrRect {
+        GrRect::new(self.position, self.viewport)
+    }
+
+    pub fn world_to_screen(&self, point: GrPoint) -> GrPoint {
+        point - self.position.to_vector()
+    }
+
+    pub fn screen_to_world(&self, point: GrPoint) -> GrPoint {
+        point + self.position.to_vector()
+    }
+
+    /// Sets the sprite to keep on screen, or None to stop following.
+    pub fn follow(&mut self, target: Option<Sprite>) {
+        self.target = target;
+    }
+
+    /// Sets the area of the screen the target can move around in without the camera moving,
+    /// or None to keep the target centered.
+    pub fn set_dead_zone(&mut self, dead_zone: Option<GrRect>) {
+        self.dead_zone = dead_zone;
+    }
+
+    /// Keeps the camera from showing anything outside `bounds`.  Worlds narrower or shorter
+    /// than the screen are centered.
+    pub fn set_bounds(&mut self, bounds: Option<GrRect>) {
+        self.bounds = bounds;
+        self.position = self.clamp(self.position);
+    }
+
+    /// Sets how long the camera takes to cover half the distance to where it should be when
+    /// following, for smooth movement.  0 moves it there immediately.
+    pub fn set_smoothing(&mut self, half_life_ms: f32) {
+        self.smoothing_ms = half_life_ms.max(0.0);
+    }
+
+    /// Shakes the display by up to `magnitude` pixels, dying away over `duration_ms`.  Does
+    /// nothing when the player has asked for reduced flashing in the system settings.
+    pub fn shake(&mut self, magnitude: f32, duration_ms: usize) -> Result<(), Error> {
+        if System::get().get_reduced_flashing()? {
+            return Ok(());
+        }
+        let stronger = match &self.shake {
+            Some(shake) => magnitude >= shake.current_magnitude(),
+            None => true,
+        };
+        if stronger {
+            self.shake = Some(Shake {
+                magnitude,
+                duration_ms,
+                elapsed_ms: 0,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn is_shaking(&self) -> bool {
+        self.shake.is_some()
+    }
+
+    /// Makes `sprite` scroll `factor` times as fast as the world: (0.5, 0.5) for a distant
+    /// background, (0.0, 0.0) to stay still on screen.  `position` is where the sprite's center
+    /// is when the camera is at the world origin.
+    pub fn add_parallax_sprite(
+        &mut self,
+        sprite: &Sprite,
+        position: GrPoint,
+        factor: GrVector,
+    ) -> Result<(), Error> {
+        sprite.set_ignores_draw_offset(true)?;
+        self.parallax.push(ParallaxSprite {
+            sprite: sprite.clone(),
+            position,
+            factor,
+        });
+        self.update_parallax()
+    }
+
+    pub fn remove_parallax_sprite(&mut self, sprite: &Sprite) -> Result<(), Error> {
+        sprite.set_ignores_draw_offset(false)?;
+        self.parallax.retain(|layer| layer.sprite != *sprite);
+        Ok(())
+    }
+
+    /// Keeps `sprite` fixed on screen however the camera moves.
+    pub fn add_hud_sprite(&self, sprite: &Sprite) -> Result<(), Error> {
+        sprite.set_ignores_draw_offset(true)
+    }
+
+    /// How far to offset drawing for something that scrolls `factor` times as fast as the
+    /// world, for drawing parallax layers without sprites.
+    pub fn parallax_offset(&self, factor: GrVector) -> ScreenVector {
+        ScreenVector::new(
+            -libm::roundf(self.position.x * factor.x) as i32,
+            -libm::roundf(self.position.y * factor.y) as i32,
+        )
+    }
+
+    /// Moves the camera toward its target, updates parallax sprites and the shake, and sets
+    /// the draw offset.  `delta_ms` is the time since the last update.
+    pub fn update(&mut self, delta_ms: usize) -> Result<(), Error> {
+        if let Some(goal) = self.follow_goal()? {
+            let goal = self.clamp(goal);
+            self.position = if self.smoothing_ms > 0.0 {
+                let remaining = libm::powf(0.5, delta_ms as f32 / self.smoothing_ms);
+                goal.lerp(self.position, remaining)
+            } else {
+                goal
+            };
+        }
+        self.position = self.clamp(self.position);
+
+        Graphics::get().set_draw_offset(self.parallax_offset(GrVector::new(1.0, 1.0)))?;
+        self.update_parallax()?;
+        self.update_shake(delta_ms)
+    }
+
+    /// Returns where the camera should be to keep the target in the dead zone.
+    fn follow_goal(&self) -> Result<Option<GrPoint>, Error> {
+        let target = match &self.target {
+            Some(target) => target,
+            None => return Ok(None),
+        };
+        let (x, y) = target.get_position()?;
+        let on_screen = self.world_to_screen(GrPoint::new(x, y));
+        let dead_zone = self.dead_zone.unwrap_or_else(|| {
+            GrRect::new((self.viewport.to_vector() / 2.0).to_point(), GrSize::zero())
+        });
+
+        let mut goal = self.position;
+        if on_screen.x < dead_zone.min_x() {
+            goal.x -= dead_zone.min_x() - on_screen.x;
+        } else if on_screen.x > dead_zone.max_x() {
+            goal.x += on_screen.x - dead_zone.max_x();
+        }
+        if on_screen.y < dead_zone.min_y() {
+            goal.y -= dead_zone.min_y() - on_screen.y;
+        } else if on_screen.y > dead_zone.max_y() {
+            goal.y += on_screen.y - dead_zone.max_y();
+        }
+        Ok(Some(goal))
+    }
+
+    fn clamp(&self, position: GrPoint) -> GrPoint {
+        let bounds = match self.bounds {
+            Some(bounds) => bounds,
+            None => return position,
+        };
+        let clamp_axis = |value: f32, min: f32, size: f32, viewport: f32| {
+            if size <= viewport {
+                min - (viewport - size) / 2.0
+            } else {
+                value.clamp(min, min + size - viewport)
+            }
+        };
+        GrPoint::new(
+            clamp_axis(
+                position.x,
+                bounds.min_x(),
+                bounds.size.width,
+                self.viewport.width,
+            ),
+            clamp_axis(
+                position.y,
+                bounds.min_y(),
+                bounds.size.height,
+                self.viewport.height,
+            ),
+        )
+    }
+
+    fn update_parallax(&mut self) -> Result<(), Error> {
+        for layer in &mut self.parallax {
+            let x = layer.position.x - self.position.x * layer.factor.x;
+            let y = layer.position.y - self.position.y * layer.factor.y;
+            layer.sprite.move_to(x, y)?;
+        }
+        Ok(())
+    }
+
+    fn update_shake(&mut self, delta_ms: usize) -> Result<(), Error> {
+        let shake = match &mut self.shake {
+            Some(shake) => shake,
+            None => return Ok(()),
+        };
+        shake.elapsed_ms += delta_ms;
+        let magnitude = shake.current_magnitude();
+        let offset = if magnitude > 0.0 {
+            let x = next_random(&mut self.seed) * magnitude;
+            let y = next_random(&mut self.seed) * magnitude;
+            ScreenPoint::new(libm::roundf(x) as i32, libm::roundf(y) as i32)
+        } else {
+            self.shake = None;
+            ScreenPoint::zero()
+        };
+        Display::get().set_offset(offset)
+    }
+}
+
+impl Shake {
+    fn current_magnitude(&self) -> f32 {
+        if self.elapsed_ms >= self.duration_ms {
+            return 0.0;
+        }
+        let remaining = 1.0 - self.elapsed_ms as f32 / self.duration_ms as f32;
+        // Ease out so the shake fades rather than stopping abruptly.
+        self.magnitude * remaining * remaining
+    }
+}
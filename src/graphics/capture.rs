@@ -0,0 +1,423 @@
+//! Encoders for saving 1-bit images: PNG and `.pdi` for single bitmaps, and animated GIF for
+//! sequences of frames captured with `FrameRecorder`.
+//!
+//! For example, to save what's on screen:
+//!
+//! ```ignore
+//! Graphics::get().save_screenshot("screenshot.png")?;
+//! ```
+
+use {
+    super::{Bitmap, BitmapPixels, Graphics, LCD_COLUMNS, LCD_ROWS, LCD_ROWSIZE},
+    crate::file::FileSystem,
+    alloc::vec::Vec,
+    anyhow::{ensure, Error},
+};
+
+impl Bitmap {
+    /// Encodes the bitmap as a PNG.  Bitmaps without a mask are written as 1-bit greyscale;
+    /// bitmaps with a mask use a 2-bit palette with a transparent entry.
+    pub fn encode_png(&self) -> Result<Vec<u8>, Error> {
+        let inner = self.inner.borrow();
+        let pixels = inner.get_pixels()?;
+        Ok(encode_png(&pixels))
+    }
+
+    /// Encodes the bitmap and writes it to `path` in the data folder as a PNG.
+    pub fn write_png(&self, path: &str) -> Result<(), Error> {
+        FileSystem::get().write_file(path, &self.encode_png()?)
+    }
+
+    /// Encodes the bitmap in the uncompressed layout of the compiled `.pdi` images produced by
+    /// `pdc`, so it can be loaded back with `Graphics::load_bitmap`.
+    pub fn encode_pdi(&self) -> Result<Vec<u8>, Error> {
+        let inner = self.inner.borrow();
+        let pixels = inner.get_pixels()?;
+        Ok(encode_pdi(&pixels))
+    }
+
+    /// Encodes the bitmap and writes it to `path` in the data folder as a `.pdi`.
+    pub fn write_pdi(&self, path: &str) -> Result<(), Error> {
+        FileSystem::get().write_file(path, &self.encode_pdi()?)
+    }
+}
+
+impl Graphics {
+    /// Saves the last frame sent to the screen to `path` in the data folder as a PNG.
+    pub fn save_screenshot(&self, path: &str) -> Result<(), Error> {
+        self.display_buffer_bitmap()?.write_png(path)
+    }
+}
+
+/// Records consecutive frames from the display and saves them as an animated GIF, e.g. for bug
+/// reports or store pages.
+///
+/// Call `capture` once per frame; it copies the last frame sent to the screen, so it can be
+/// called at any point in `Game::update`.  Once `frame_count` frames have been captured, further
+/// calls are ignored and `write_gif` can be used to save them.
+#[derive(Debug)]
+pub struct FrameRecorder {
+    frames: Vec<Vec<u8>>,
+    frame_count: usize,
+    frame_delay_ms: usize,
+}
+
+impl FrameRecorder {
+    /// Creates a recorder for `frame_count` frames, each shown for `frame_delay_ms` in the GIF.
+    /// GIF delays are in hundredths of a second, so the delay is rounded to the nearest 10ms.
+    pub fn new(frame_count: usize, frame_delay_ms: usize) -> Self {
+        Self {
+            frames: Vec::with_capacity(frame_count),
+            frame_count,
+            frame_delay_ms,
+        }
+    }
+
+    /// Copies the current display frame.  Returns true once all frames have been captured.
+    pub fn capture(&mut self) -> Result<bool, Error> {
+        if !self.is_finished() {
+            let frame = Graphics::get().get_display_frame()?;
+            self.frames.push(frame.to_vec());
+        }
+        Ok(self.is_finished())
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frames.len() >= self.frame_count
+    }
+
+    /// Returns the number of frames captured so far.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Discards the captured frames so recording can start again.
+    pub fn reset(&mut self) {
+        self.frames.clear();
+    }
+
+    /// Encodes the captured frames as a looping animated GIF.
+    pub fn encode_gif(&self) -> Result<Vec<u8>, Error> {
+        ensure!(!self.frames.is_empty(), "No frames captured");
+        let delay_cs = ((self.frame_delay_ms + 5) / 10) as u16;
+        let mut gif = GifEncoder::new(LCD_COLUMNS as u16, LCD_ROWS as u16);
+        for frame in &self.frames {
+            let pixels = BitmapPixels {
+                width: LCD_COLUMNS as usize,
+                height: LCD_ROWS as usize,
+                rowbytes: LCD_ROWSIZE as usize,
+                data: frame,
+                mask: None,
+            };
+            gif.add_frame(&pixels, delay_cs);
+        }
+        Ok(gif.finish())
+    }
+
+    /// Encodes the captured frames and writes them to `path` in the data folder.
+    pub fn write_gif(&self, path: &str) -> Result<(), Error> {
+        FileSystem::get().write_file(path, &self.encode_gif()?)
+    }
+}
+
+fn pixel(row: &[u8], x: usize) -> bool {
+    row[x / 8] & (0x80 >> (x % 8)) != 0
+}
+
+const fn make_crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xedb8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+}
+
+static CRC_TABLE: [u32; 256] = make_crc_table();
+
+/// CRC-32 as used by PNG and zlib.
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+    crc32_update(0xffff_ffff, bytes) ^ 0xffff_ffff
+}
+
+fn crc32_update(mut crc: u32, bytes: &[u8]) -> u32 {
+    for &b in bytes {
+        crc = CRC_TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+pub(crate) fn adler32(bytes: &[u8]) -> u32 {
+    let mut a = 1u32;
+    let mut b = 0u32;
+    for chunk in bytes.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+/// Wraps `data` in a zlib stream of uncompressed deflate blocks.  1-bit images are small enough
+/// that compressing them isn't worth the code or the CPU time on device.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + data.len() / 65535 * 5 + 11);
+    out.extend_from_slice(&[0x78, 0x01]);
+    let mut chunks = data.chunks(65535).peekable();
+    if chunks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(chunk) = chunks.next() {
+        let last = chunks.peek().is_none();
+        let len = chunk.len() as u16;
+        out.push(last as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(chunk);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn png_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32_update(crc32_update(0xffff_ffff, kind), data) ^ 0xffff_ffff;
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+pub(crate) fn encode_png(pixels: &BitmapPixels) -> Vec<u8> {
+    let has_mask = pixels.mask.is_some();
+    let bit_depth = if has_mask { 2 } else { 1 };
+    let color_type = if has_mask { 3 } else { 0 };
+    let png_rowbytes = (pixels.width * bit_depth + 7) / 8;
+
+    let mut raw = Vec::with_capacity((png_rowbytes + 1) * pixels.height);
+    for y in 0..pixels.height {
+        // Filter type: none.
+        raw.push(0);
+        let row = pixels.row(y);
+        match pixels.mask_row(y) {
+            None => raw.extend_from_slice(&row[..png_rowbytes]),
+            Some(mask_row) => {
+                let start = raw.len();
+                raw.resize(start + png_rowbytes, 0);
+                for x in 0..pixels.width {
+                    // Palette: 0 black, 1 white, 2 transparent.
+                    let index = if !pixel(mask_row, x) {
+                        2
+                    } else {
+                        pixel(row, x) as u8
+                    };
+                    raw[start + x / 4] |= index << (6 - 2 * (x % 4));
+                }
+            }
+        }
+    }
+
+    let mut out = Vec::with_capacity(raw.len() + 128);
+    out.extend_from_slice(b"\x89PNG\r\n\x1a\n");
+    let mut ihdr = [0u8; 13];
+    ihdr[0..4].copy_from_slice(&(pixels.width as u32).to_be_bytes());
+    ihdr[4..8].copy_from_slice(&(pixels.height as u32).to_be_bytes());
+    ihdr[8] = bit_depth as u8;
+    ihdr[9] = color_type;
+    png_chunk(&mut out, b"IHDR", &ihdr);
+    if has_mask {
+        png_chunk(&mut out, b"PLTE", &[0, 0, 0, 0xff, 0xff, 0xff, 0, 0, 0]);
+        png_chunk(&mut out, b"tRNS", &[0xff, 0xff, 0]);
+    }
+    png_chunk(&mut out, b"IDAT", &zlib_stored(&raw));
+    png_chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+/// Writes the layout described by the community's reverse engineering of `pdc` output: a
+/// "Playdate IMG" header with no compression flag, then a single cell header followed by the
+/// image rows and, if present, the mask rows.
+pub(crate) fn encode_pdi(pixels: &BitmapPixels) -> Vec<u8> {
+    let stride = (pixels.width + 7) / 8;
+    let mut out = Vec::with_capacity(32 + stride * pixels.height * 2);
+    out.extend_from_slice(b"Playdate IMG");
+    out.extend_from_slice(&0u32.to_le_bytes());
+    let flags: u16 = if pixels.mask.is_some() { 3 } else { 0 };
+    for value in [
+        pixels.width as u16,
+        pixels.height as u16,
+        stride as u16,
+        0, // clip left
+        0, // clip right
+        0, // clip top
+        0, // clip bottom
+        flags,
+    ] {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+    for y in 0..pixels.height {
+        out.extend_from_slice(&pixels.row(y)[..stride]);
+    }
+    if pixels.mask.is_some() {
+        for y in 0..pixels.height {
+            out.extend_from_slice(&pixels.mask_row(y).unwrap_or_default()[..stride]);
+        }
+    }
+    out
+}
+
+const GIF_MIN_CODE_SIZE: u8 = 2;
+const GIF_MAX_CODES: usize = 4096;
+
+/// Writes GIF89a files with a black and white palette.
+pub(crate) struct GifEncoder {
+    out: Vec<u8>,
+    width: u16,
+    height: u16,
+}
+
+impl GifEncoder {
+    pub fn new(width: u16, height: u16) -> Self {
+        let mut out = Vec::new();
+        out.extend_from_slice(b"GIF89a");
+        out.extend_from_slice(&width.to_le_bytes());
+        out.extend_from_slice(&height.to_le_bytes());
+        // Global color table of 2 entries, 1 bit of color resolution.
+        out.extend_from_slice(&[0x80, 0, 0]);
+        out.extend_from_slice(&[0, 0, 0, 0xff, 0xff, 0xff]);
+        // Loop forever.
+        out.extend_from_slice(&[0x21, 0xff, 0x0b]);
+        out.extend_from_slice(b"NETSCAPE2.0");
+        out.extend_from_slice(&[0x03, 0x01, 0x00, 0x00, 0x00]);
+        Self { out, width, height }
+    }
+
+    pub fn add_frame(&mut self, pixels: &BitmapPixels, delay_cs: u16) {
+        // Graphic control extension: no disposal, no transparency.
+        self.out.extend_from_slice(&[0x21, 0xf9, 0x04, 0x04]);
+        self.out.extend_from_slice(&delay_cs.to_le_bytes());
+        self.out.extend_from_slice(&[0x00, 0x00]);
+        // Image descriptor covering the whole canvas.
+        self.out.push(0x2c);
+        self.out.extend_from_slice(&[0, 0, 0, 0]);
+        self.out.extend_from_slice(&self.width.to_le_bytes());
+        self.out.extend_from_slice(&self.height.to_le_bytes());
+        self.out.push(0);
+
+        self.out.push(GIF_MIN_CODE_SIZE);
+        let width = pixels.width.min(self.width as usize);
+        let height = pixels.height.min(self.height as usize);
+        let indexes = (0..height).flat_map(|y| {
+            let row = pixels.row(y);
+            (0..width).map(move |x| pixel(row, x) as u8)
+        });
+        let data = lzw_encode(indexes);
+        for block in data.chunks(255) {
+            self.out.push(block.len() as u8);
+            self.out.extend_from_slice(block);
+        }
+        self.out.push(0);
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        self.out.push(0x3b);
+        self.out
+    }
+}
+
+struct BitWriter {
+    out: Vec<u8>,
+    bits: u32,
+    count: u8,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, size: u8) {
+        self.bits |= (code as u32) << self.count;
+        self.count += size;
+        while self.count >= 8 {
+            self.out.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.bits as u8);
+        }
+        self.out
+    }
+}
+
+/// GIF-flavored LZW over palette indexes of `GIF_MIN_CODE_SIZE` bits.
+fn lzw_encode(mut indexes: impl Iterator<Item = u8>) -> Vec<u8> {
+    const ALPHABET: usize = 1 << GIF_MIN_CODE_SIZE;
+    let clear = ALPHABET as u16;
+    let end = clear + 1;
+
+    // children[code * ALPHABET + index] is the code for `code` followed by `index`, or 0 if
+    // there isn't one yet; 0 is never a valid child since children start after `end`.
+    let mut children = alloc::vec![0u16; GIF_MAX_CODES * ALPHABET];
+    let mut next_code = end + 1;
+    let mut code_size = GIF_MIN_CODE_SIZE + 1;
+    let mut writer = BitWriter {
+        out: Vec::new(),
+        bits: 0,
+        count: 0,
+    };
+
+    writer.write(clear, code_size);
+    let mut prefix = match indexes.next() {
+        Some(index) => index as u16,
+        None => {
+            writer.write(end, code_size);
+            return writer.finish();
+        }
+    };
+    for index in indexes {
+        let slot = prefix as usize * ALPHABET + index as usize;
+        if children[slot] != 0 {
+            prefix = children[slot];
+            continue;
+        }
+        writer.write(prefix, code_size);
+        if (next_code as usize) < GIF_MAX_CODES {
+            if next_code == 1 << code_size {
+                code_size += 1;
+            }
+            children[slot] = next_code;
+            next_code += 1;
+        } else {
+            writer.write(clear, code_size);
+            children.iter_mut().for_each(|child| *child = 0);
+            next_code = end + 1;
+            code_size = GIF_MIN_CODE_SIZE + 1;
+        }
+        prefix = index as u16;
+    }
+    writer.write(prefix, code_size);
+    // The decoder adds a table entry after reading the last code, which may widen the codes.
+    if (next_code as usize) < GIF_MAX_CODES && next_code == 1 << code_size {
+        code_size += 1;
+    }
+    writer.write(end, code_size);
+    writer.finish()
+}
@@ -0,0 +1,648 @@
+//! Decoders for creating bitmaps at runtime from image data, e.g. files downloaded into the data
+//! folder and read with `FileSystem::read_file`, or data embedded with `include_bytes!`.
+//!
+//! ```ignore
+//! let logo = Bitmap::from_png_1bit(include_bytes!("logo.png"), Dither::Atkinson)?;
+//! ```
+
+use {
+    super::{
+        capture::{adler32, crc32},
+        dither::{dither, Dither},
+        Bitmap, Graphics, LCDColor, LCDSolidColor,
+    },
+    crate::geometry::ScreenSize,
+    alloc::{vec, vec::Vec},
+    anyhow::{anyhow, bail, ensure, Error},
+    euclid::size2,
+};
+
+/// The largest width or height decoded, well beyond what fits in the Playdate's memory, so
+/// sizes from untrusted headers can't overflow the buffer size calculations.
+const MAX_SIDE: usize = 4096;
+
+impl Bitmap {
+    /// Creates a bitmap from 1-bit rows of `rowbytes` bytes, most significant bit first, with
+    /// set bits white; the same layout the Playdate uses.
+    pub fn from_raw_1bpp(data: &[u8], size: ScreenSize, rowbytes: usize) -> Result<Bitmap, Error> {
+        ensure!(
+            size.width > 0 && size.height > 0,
+            "Invalid bitmap size {:?}",
+            size
+        );
+        let width = size.width as usize;
+        let height = size.height as usize;
+        ensure!(
+            rowbytes >= (width + 7) / 8,
+            "rowbytes {} too small for width {}",
+            rowbytes,
+            width
+        );
+        let len = rowbytes
+            .checked_mul(height)
+            .ok_or_else(|| anyhow!("Bitmap size {:?} is too large", size))?;
+        ensure!(
+            data.len() >= len,
+            "Expected {} bytes of bitmap data, got {}",
+            len,
+            data.len()
+        );
+        new_bitmap_1bpp(width, height, rowbytes, data, None)
+    }
+
+    /// Creates a bitmap from a PBM image, in either the ASCII (`P1`) or binary (`P4`) format.
+    pub fn from_pbm(data: &[u8]) -> Result<Bitmap, Error> {
+        let mut header = PnmHeader { data, pos: 0 };
+        let magic = header.token()?;
+        let binary = match magic {
+            b"P1" => false,
+            b"P4" => true,
+            _ => bail!("Not a PBM image"),
+        };
+        let width = header.number()?;
+        let height = header.number()?;
+        ensure!(
+            width > 0 && height > 0 && width <= MAX_SIDE && height <= MAX_SIDE,
+            "Invalid PBM size {}x{}",
+            width,
+            height
+        );
+
+        let stride = (width + 7) / 8;
+        let mut rows = vec![0u8; stride * height];
+        if binary {
+            // Exactly one whitespace character separates the header from the data.
+            let start = header.pos + 1;
+            ensure!(
+                data.len() >= start + stride * height,
+                "PBM data is truncated"
+            );
+            rows.copy_from_slice(&data[start..start + stride * height]);
+        } else {
+            for i in 0..width * height {
+                let bit = header.bit()?;
+                if bit {
+                    rows[(i / width) * stride + (i % width) / 8] |= 0x80 >> (i % width % 8);
+                }
+            }
+        }
+        // PBM uses 1 for black; the Playdate uses 1 for white.
+        for (i, byte) in rows.iter_mut().enumerate() {
+            *byte = !*byte;
+            if i % stride == stride - 1 && width % 8 != 0 {
+                *byte &= 0xff << (8 - width % 8);
+            }
+        }
+        new_bitmap_1bpp(width, height, stride, &rows, None)
+    }
+
+    /// Creates a bitmap from a PNG of any color type.  Colors are converted to greyscale and
+    /// reduced to black and white with `dither`; already 1-bit images come through unchanged with
+    /// any dither mode.  Pixels that are more than half transparent are masked out.  Interlaced
+    /// PNGs are not supported.
+    pub fn from_png_1bit(data: &[u8], dither: Dither) -> Result<Bitmap, Error> {
+        let image = decode_png(data)?;
+        let packed = self::dither(&image.luma, image.width, image.height, dither);
+        let (width, height) = (image.width, image.height);
+        let stride = (width + 7) / 8;
+        let mask = image.alpha.map(|alpha| {
+            let mut mask = vec![0u8; stride * height];
+            for (i, &a) in alpha.iter().enumerate() {
+                if a >= 128 {
+                    let (x, y) = (i % width, i / width);
+                    mask[y * stride + x / 8] |= 0x80 >> (x % 8);
+                }
+            }
+            mask
+        });
+        new_bitmap_1bpp(width, height, stride, &packed, mask.as_deref())
+    }
+}
+
+/// Allocates a bitmap and copies 1-bit rows of `stride` bytes, and optionally mask rows, into it.
+fn new_bitmap_1bpp(
+    width: usize,
+    height: usize,
+    stride: usize,
+    data: &[u8],
+    mask: Option<&[u8]>,
+) -> Result<Bitmap, Error> {
+    // Bitmaps created with a clear background have a mask.
+    let background = if mask.is_some() {
+        LCDSolidColor::kColorClear
+    } else {
+        LCDSolidColor::kColorBlack
+    };
+    let bitmap = Graphics::get().new_bitmap(
+        size2(width as i32, height as i32),
+        LCDColor::Solid(background),
+    )?;
+    {
+        let mut inner = bitmap.inner.borrow_mut();
+        let pixels = inner.get_pixels_mut()?;
+        let copy = (width + 7) / 8;
+        for y in 0..height {
+            let dest = y * pixels.rowbytes;
+            pixels.data[dest..dest + copy].copy_from_slice(&data[y * stride..y * stride + copy]);
+        }
+        if let Some(mask) = mask {
+            let dest_mask = pixels
+                .mask
+                .ok_or_else(|| anyhow!("Bitmap created without a mask"))?;
+            for y in 0..height {
+                let dest = y * pixels.rowbytes;
+                dest_mask[dest..dest + copy].copy_from_slice(&mask[y * stride..y * stride + copy]);
+            }
+        }
+    }
+    Ok(bitmap)
+}
+
+struct PnmHeader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> PnmHeader<'a> {
+    fn skip_whitespace_and_comments(&mut self) {
+        while self.pos < self.data.len() {
+            match self.data[self.pos] {
+                b'#' => {
+                    while self.pos < self.data.len() && self.data[self.pos] != b'\n' {
+                        self.pos += 1;
+                    }
+                }
+                b' ' | b'\t' | b'\r' | b'\n' => self.pos += 1,
+                _ => break,
+            }
+        }
+    }
+
+    fn token(&mut self) -> Result<&'a [u8], Error> {
+        self.skip_whitespace_and_comments();
+        let start = self.pos;
+        while self.pos < self.data.len() && !self.data[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+        ensure!(self.pos > start, "PBM header is truncated");
+        Ok(&self.data[start..self.pos])
+    }
+
+    fn number(&mut self) -> Result<usize, Error> {
+        let token = self.token()?;
+        core::str::from_utf8(token)
+            .ok()
+            .and_then(|token| token.parse().ok())
+            .ok_or_else(|| anyhow!("Invalid number in PBM header"))
+    }
+
+    /// Reads one pixel of an ASCII PBM; digits needn't be separated by whitespace.
+    fn bit(&mut self) -> Result<bool, Error> {
+        self.skip_whitespace_and_comments();
+        let bit = match self.data.get(self.pos) {
+            Some(b'0') => false,
+            Some(b'1') => true,
+            _ => bail!("PBM data is truncated"),
+        };
+        self.pos += 1;
+        Ok(bit)
+    }
+}
+
+/// A PNG converted to 8-bit greyscale, with optional 8-bit alpha.
+struct DecodedPng {
+    width: usize,
+    height: usize,
+    luma: Vec<u8>,
+    alpha: Option<Vec<u8>>,
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn decode_png(data: &[u8]) -> Result<DecodedPng, Error> {
+    ensure!(
+        data.len() >= 8 && &data[..8] == b"\x89PNG\r\n\x1a\n",
+        "Not a PNG image"
+    );
+    let mut pos = 8;
+    let mut header = None;
+    let mut palette: &[u8] = &[];
+    let mut transparency: &[u8] = &[];
+    let mut compressed = Vec::new();
+    loop {
+        ensure!(data.len() - pos >= 12, "PNG data is truncated");
+        let len = be_u32(&data[pos..]) as usize;
+        // The length is untrusted, and could overflow on the 32-bit Playdate.
+        let end = pos
+            .checked_add(12)
+            .and_then(|end| end.checked_add(len))
+            .ok_or_else(|| anyhow!("PNG chunk is too long"))?;
+        ensure!(end <= data.len(), "PNG data is truncated");
+        let kind = &data[pos + 4..pos + 8];
+        let body = &data[pos + 8..end - 4];
+        ensure!(
+            crc32(&data[pos + 4..end - 4]) == be_u32(&data[end - 4..]),
+            "PNG chunk has a bad checksum"
+        );
+        pos = end;
+        match kind {
+            b"IHDR" => {
+                ensure!(len == 13, "Invalid PNG header");
+                header = Some(body);
+            }
+            b"PLTE" => palette = body,
+            b"tRNS" => transparency = body,
+            b"IDAT" => compressed.extend_from_slice(body),
+            b"IEND" => break,
+            _ => {}
+        }
+    }
+
+    let header = header.ok_or_else(|| anyhow!("PNG has no header"))?;
+    let width = be_u32(&header[0..]) as usize;
+    let height = be_u32(&header[4..]) as usize;
+    let bit_depth = header[8] as usize;
+    let color_type = header[9];
+    ensure!(width > 0 && height > 0, "Invalid PNG size");
+    ensure!(
+        width <= MAX_SIDE && height <= MAX_SIDE,
+        "PNG size {}x{} is too large",
+        width,
+        height
+    );
+    ensure!(header[12] == 0, "Interlaced PNGs are not supported");
+    let channels = match color_type {
+        0 | 3 => 1,
+        2 => 3,
+        4 => 2,
+        6 => 4,
+        _ => bail!("Invalid PNG color type {}", color_type),
+    };
+    ensure!(
+        matches!(bit_depth, 1 | 2 | 4 | 8 | 16),
+        "Invalid PNG bit depth {}",
+        bit_depth
+    );
+
+    let rowbytes = (width * channels * bit_depth + 7) / 8;
+    let bpp = ((channels * bit_depth + 7) / 8).max(1);
+    let raw = zlib_decompress(&compressed, (rowbytes + 1) * height)?;
+    ensure!(
+        raw.len() >= (rowbytes + 1) * height,
+        "PNG image data is truncated"
+    );
+    let pixels = unfilter(&raw, rowbytes, height, bpp)?;
+
+    let max = (1u32 << bit_depth) - 1;
+    let sample = |row: &[u8], index: usize| -> u32 {
+        match bit_depth {
+            16 => row[index * 2] as u32,
+            8 => row[index] as u32,
+            _ => {
+                let bit = index * bit_depth;
+                (row[bit / 8] as u32 >> (8 - bit_depth - bit % 8)) & max
+            }
+        }
+    };
+    // Full-precision sample, for comparing with tRNS values.
+    let raw_sample = |row: &[u8], index: usize| -> u32 {
+        if bit_depth == 16 {
+            u16::from_be_bytes([row[index * 2], row[index * 2 + 1]]) as u32
+        } else {
+            sample(row, index)
+        }
+    };
+    let to_8bit = |value: u32| -> u32 {
+        if bit_depth >= 8 {
+            value
+        } else {
+            value * 255 / max
+        }
+    };
+    let luminance = |r: u32, g: u32, b: u32| ((r * 299 + g * 587 + b * 114) / 1000) as u8;
+    let trns = |i: usize| {
+        transparency
+            .get(i * 2..i * 2 + 2)
+            .map(|v| u16::from_be_bytes([v[0], v[1]]) as u32)
+    };
+
+    let mut luma = Vec::with_capacity(width * height);
+    let mut alpha = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let row = &pixels[y * rowbytes..(y + 1) * rowbytes];
+        for x in 0..width {
+            let i = x * channels;
+            let (l, a) = match color_type {
+                0 => {
+                    let transparent = trns(0) == Some(raw_sample(row, i));
+                    (
+                        to_8bit(sample(row, i)) as u8,
+                        if transparent { 0 } else { 255 },
+                    )
+                }
+                2 => {
+                    let transparent = transparency.len() >= 6
+                        && (0..3).all(|c| trns(c) == Some(raw_sample(row, i + c)));
+                    (
+                        luminance(sample(row, i), sample(row, i + 1), sample(row, i + 2)),
+                        if transparent { 0 } else { 255 },
+                    )
+                }
+                3 => {
+                    let index = sample(row, i) as usize;
+                    let rgb = palette
+                        .get(index * 3..index * 3 + 3)
+                        .ok_or_else(|| anyhow!("PNG palette index {} out of range", index))?;
+                    (
+                        luminance(rgb[0] as u32, rgb[1] as u32, rgb[2] as u32),
+                        transparency.get(index).copied().unwrap_or(255),
+                    )
+                }
+                4 => (sample(row, i) as u8, sample(row, i + 1) as u8),
+                _ => (
+                    luminance(sample(row, i), sample(row, i + 1), sample(row, i + 2)),
+                    sample(row, i + 3) as u8,
+                ),
+            };
+            luma.push(l);
+            alpha.push(a);
+        }
+    }
+    let has_alpha = alpha.iter().any(|&a| a < 128);
+    Ok(DecodedPng {
+        width,
+        height,
+        luma,
+        alpha: if has_alpha { Some(alpha) } else { None },
+    })
+}
+
+fn unfilter(raw: &[u8], rowbytes: usize, height: usize, bpp: usize) -> Result<Vec<u8>, Error> {
+    let mut out = vec![0u8; rowbytes * height];
+    for y in 0..height {
+        let filter = raw[y * (rowbytes + 1)];
+        let src = &raw[y * (rowbytes + 1) + 1..(y + 1) * (rowbytes + 1)];
+        let (done, rest) = out.split_at_mut(y * rowbytes);
+        let prior = if y > 0 {
+            &done[(y - 1) * rowbytes..]
+        } else {
+            &[][..]
+        };
+        let row = &mut rest[..rowbytes];
+        for x in 0..rowbytes {
+            let a = if x >= bpp { row[x - bpp] as i16 } else { 0 };
+            let b = prior.get(x).copied().unwrap_or(0) as i16;
+            let c = if x >= bpp {
+                prior.get(x - bpp).copied().unwrap_or(0) as i16
+            } else {
+                0
+            };
+            let predicted = match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => (a + b) / 2,
+                4 => {
+                    let p = a + b - c;
+                    let (pa, pb, pc) = ((p - a).abs(), (p - b).abs(), (p - c).abs());
+                    if pa <= pb && pa <= pc {
+                        a
+                    } else if pb <= pc {
+                        b
+                    } else {
+                        c
+                    }
+                }
+                _ => bail!("Invalid PNG filter type {}", filter),
+            };
+            row[x] = src[x].wrapping_add(predicted as u8);
+        }
+    }
+    Ok(out)
+}
+
+/// Decompresses a zlib stream, failing if it would produce more than `limit` bytes.
+pub(crate) fn zlib_decompress(data: &[u8], limit: usize) -> Result<Vec<u8>, Error> {
+    ensure!(data.len() >= 6, "zlib data is truncated");
+    ensure!(
+        data[0] & 0x0f == 8 && (data[0] as u16 * 256 + data[1] as u16) % 31 == 0,
+        "Invalid zlib header"
+    );
+    ensure!(
+        data[1] & 0x20 == 0,
+        "zlib preset dictionaries are not supported"
+    );
+    let out = inflate(&data[2..], limit)?;
+    let expected = be_u32(&data[data.len() - 4..]);
+    ensure!(adler32(&out) == expected, "zlib data has a bad checksum");
+    Ok(out)
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bits: u32,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn bits(&mut self, n: u32) -> Result<u32, Error> {
+        while self.count < n {
+            let byte = *self
+                .data
+                .get(self.pos)
+                .ok_or_else(|| anyhow!("Compressed data is truncated"))?;
+            self.bits |= (byte as u32) << self.count;
+            self.pos += 1;
+            self.count += 8;
+        }
+        let value = self.bits & ((1u32 << n) - 1);
+        self.bits >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+
+    fn align_to_byte(&mut self) {
+        self.bits = 0;
+        self.count = 0;
+    }
+}
+
+/// Canonical Huffman code, as number of codes of each length and the symbols in code order.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; 16];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0u16; 16];
+        for len in 1..15 {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        Self { counts, symbols }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, Error> {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for len in 1..16 {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - count < first {
+                return Ok(self.symbols[(index + (code - first)) as usize]);
+            }
+            index += count;
+            first += count;
+            first <<= 1;
+            code <<= 1;
+        }
+        bail!("Invalid Huffman code")
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Decompresses a raw deflate stream of at most `limit` bytes.
+fn inflate(data: &[u8], limit: usize) -> Result<Vec<u8>, Error> {
+    let mut reader = BitReader {
+        data,
+        pos: 0,
+        bits: 0,
+        count: 0,
+    };
+    let mut out = Vec::new();
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => {
+                reader.align_to_byte();
+                let header = reader
+                    .data
+                    .get(reader.pos..reader.pos + 4)
+                    .ok_or_else(|| anyhow!("Compressed data is truncated"))?;
+                let len = u16::from_le_bytes([header[0], header[1]]) as usize;
+                let nlen = u16::from_le_bytes([header[2], header[3]]) as usize;
+                ensure!(len == !nlen & 0xffff, "Invalid stored block length");
+                reader.pos += 4;
+                let block = reader
+                    .data
+                    .get(reader.pos..reader.pos + len)
+                    .ok_or_else(|| anyhow!("Compressed data is truncated"))?;
+                ensure!(out.len() + len <= limit, "Decompressed data is too long");
+                out.extend_from_slice(block);
+                reader.pos += len;
+            }
+            1 => {
+                let mut lengths = [0u8; 288];
+                lengths[..144].iter_mut().for_each(|l| *l = 8);
+                lengths[144..256].iter_mut().for_each(|l| *l = 9);
+                lengths[256..280].iter_mut().for_each(|l| *l = 7);
+                lengths[280..].iter_mut().for_each(|l| *l = 8);
+                let literals = Huffman::new(&lengths);
+                let distances = Huffman::new(&[5; 30]);
+                inflate_block(&mut reader, &mut out, limit, &literals, &distances)?;
+            }
+            2 => {
+                let literal_count = reader.bits(5)? as usize + 257;
+                let distance_count = reader.bits(5)? as usize + 1;
+                let code_length_count = reader.bits(4)? as usize + 4;
+                let mut code_lengths = [0u8; 19];
+                for &index in &CODE_LENGTH_ORDER[..code_length_count] {
+                    code_lengths[index] = reader.bits(3)? as u8;
+                }
+                let code_length_code = Huffman::new(&code_lengths);
+
+                let mut lengths = vec![0u8; literal_count + distance_count];
+                let mut i = 0;
+                while i < lengths.len() {
+                    let symbol = code_length_code.decode(&mut reader)?;
+                    let (value, repeat) = match symbol {
+                        0..=15 => (symbol as u8, 1),
+                        16 => {
+                            ensure!(i > 0, "Repeat with no previous length");
+                            (lengths[i - 1], 3 + reader.bits(2)? as usize)
+                        }
+                        17 => (0, 3 + reader.bits(3)? as usize),
+                        _ => (0, 11 + reader.bits(7)? as usize),
+                    };
+                    ensure!(i + repeat <= lengths.len(), "Too many code lengths");
+                    lengths[i..i + repeat].iter_mut().for_each(|l| *l = value);
+                    i += repeat;
+                }
+                let literals = Huffman::new(&lengths[..literal_count]);
+                let distances = Huffman::new(&lengths[literal_count..]);
+                inflate_block(&mut reader, &mut out, limit, &literals, &distances)?;
+            }
+            _ => bail!("Invalid deflate block type"),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+fn inflate_block(
+    reader: &mut BitReader,
+    out: &mut Vec<u8>,
+    limit: usize,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Result<(), Error> {
+    loop {
+        let symbol = literals.decode(reader)? as usize;
+        if symbol < 256 {
+            ensure!(out.len() < limit, "Decompressed data is too long");
+            out.push(symbol as u8);
+        } else if symbol == 256 {
+            return Ok(());
+        } else {
+            let symbol = symbol - 257;
+            ensure!(symbol < 29, "Invalid length code");
+            let len =
+                LENGTH_BASE[symbol] as usize + reader.bits(LENGTH_EXTRA[symbol] as u32)? as usize;
+            let symbol = distances.decode(reader)? as usize;
+            ensure!(symbol < 30, "Invalid distance code");
+            let distance = DISTANCE_BASE[symbol] as usize
+                + reader.bits(DISTANCE_EXTRA[symbol] as u32)? as usize;
+            ensure!(distance <= out.len(), "Distance too far back");
+            ensure!(out.len() + len <= limit, "Decompressed data is too long");
+            let start = out.len() - distance;
+            for i in 0..len {
+                out.push(out[start + i]);
+            }
+        }
+    }
+}
@@ -0,0 +1,104 @@
+//! Conversion of 8-bit greyscale images to the 1-bit images the Playdate can display.
+
+use alloc::{vec, vec::Vec};
+
+/// How to reduce greyscale to black and white.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dither {
+    /// Pixels at least half bright become white; the rest become black.
+    Threshold,
+    /// Error diffusion that spreads all of each pixel's error to its neighbors; the smoothest
+    /// gradients, but noisy on flat areas.
+    FloydSteinberg,
+    /// Ordered dithering with a 4x4 Bayer matrix, giving 17 evenly spaced grey levels with a
+    /// regular pattern.  Stable under animation, unlike error diffusion.
+    Bayer,
+    /// Error diffusion that spreads only three quarters of the error, as on the original
+    /// Macintosh; higher contrast than Floyd-Steinberg.
+    Atkinson,
+}
+
+/// Thresholds for ordered dithering; a pixel at grey level `n` of 16 is white if `n` is greater
+/// than its entry.
+pub(crate) const BAYER_4X4: [[u8; 4]; 4] =
+    [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// Converts `width` x `height` greyscale pixels, 0 black and 255 white, into 1-bit rows of
+/// `(width + 7) / 8` bytes, most significant bit first, with set bits white.
+pub(crate) fn dither(luma: &[u8], width: usize, height: usize, mode: Dither) -> Vec<u8> {
+    let stride = (width + 7) / 8;
+    let mut out = vec![0u8; stride * height];
+    let mut set = |x: usize, y: usize| out[y * stride + x / 8] |= 0x80 >> (x % 8);
+
+    match mode {
+        Dither::Threshold => {
+            for y in 0..height {
+                for x in 0..width {
+                    if luma[y * width + x] >= 128 {
+                        set(x, y);
+                    }
+                }
+            }
+        }
+        Dither::Bayer => {
+            for y in 0..height {
+                for x in 0..width {
+                    let level = luma[y * width + x] as u32 * 17 / 256;
+                    if level > BAYER_4X4[y % 4][x % 4] as u32 {
+                        set(x, y);
+                    }
+                }
+            }
+        }
+        Dither::FloydSteinberg => {
+            // Error for the current and next rows, with a pixel of padding on each side.
+            let mut current = vec![0i16; width + 2];
+            let mut next = vec![0i16; width + 2];
+            for y in 0..height {
+                for x in 0..width {
+                    let value = luma[y * width + x] as i16 + current[x + 1] / 16;
+                    let error = if value >= 128 {
+                        set(x, y);
+                        value - 255
+                    } else {
+                        value
+                    };
+                    current[x + 2] += error * 7;
+                    next[x] += error * 3;
+                    next[x + 1] += error * 5;
+                    next[x + 2] += error;
+                }
+                core::mem::swap(&mut current, &mut next);
+                next.iter_mut().for_each(|e| *e = 0);
+            }
+        }
+        Dither::Atkinson => {
+            // Error for this row and the two below, with padding for the neighbors it reaches.
+            let mut rows = [
+                vec![0i16; width + 3],
+                vec![0i16; width + 3],
+                vec![0i16; width + 3],
+            ];
+            for y in 0..height {
+                for x in 0..width {
+                    let value = luma[y * width + x] as i16 + rows[0][x + 1];
+                    let error = if value >= 128 {
+                        set(x, y);
+                        value - 255
+                    } else {
+                        value
+                    } / 8;
+                    rows[0][x + 2] += error;
+                    rows[0][x + 3] += error;
+                    rows[1][x] += error;
+                    rows[1][x + 1] += error;
+                    rows[1][x + 2] += error;
+                    rows[2][x + 1] += error;
+                }
+                rows.rotate_left(1);
+                rows[2].iter_mut().for_each(|e| *e = 0);
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,167 @@
+use {
+    crate::system::System,
+    alloc::{rc::Rc, string::String, vec::Vec},
+    anyhow::Error,
+};
+
+/// A font loaded with `Graphics::load_font` or `Graphics::make_font_from_data`.
+// Really a wrapper around an Rc clone of the internal structure; derive Clone so it's easy to get
+// another Rc reference.  We use Rc so we don't free the font while it's still set as the current
+// font or used by a `FontFamily`.
+#[derive(Clone, Debug)]
+pub struct Font {
+    pub(crate) inner: Rc<FontInner>,
+}
+
+#[derive(Debug)]
+pub(crate) struct FontInner {
+    pub(crate) raw_font: *mut crankstart_sys::LCDFont,
+    // Fonts made from data point into it rather than copying it, so keep it alive as long as
+    // the font is.
+    data: Option<Vec<u8>>,
+}
+
+impl Drop for FontInner {
+    fn drop(&mut self) {
+        // The C API has no freeFont; fonts are allocated with the system allocator and are
+        // released the same way.
+        System::get().realloc(self.raw_font as *mut core::ffi::c_void, 0);
+    }
+}
+
+impl Font {
+    /// Takes ownership of the given font; it will be freed when the last clone is dropped.
+    pub fn new(font: *mut crankstart_sys::LCDFont) -> Result<Self, Error> {
+        anyhow::ensure!(!font.is_null(), "Null pointer passed to Font::new");
+        Ok(Self {
+            inner: Rc::new(FontInner {
+                raw_font: font,
+                data: None,
+            }),
+        })
+    }
+
+    pub(crate) fn new_with_data(
+        font: *mut crankstart_sys::LCDFont,
+        data: Vec<u8>,
+    ) -> Result<Self, Error> {
+        anyhow::ensure!(
+            !font.is_null(),
+            "Null pointer passed to Font::new_with_data"
+        );
+        Ok(Self {
+            inner: Rc::new(FontInner {
+                raw_font: font,
+                data: Some(data),
+            }),
+        })
+    }
+
+    pub(crate) fn raw_font(&self) -> *mut crankstart_sys::LCDFont {
+        self.inner.raw_font
+    }
+}
+
+impl PartialEq for Font {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+/// Which member of a `FontFamily` to use.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FontVariant {
+    Normal,
+    Bold,
+    Italic,
+}
+
+/// A set of related fonts, like `playdate.graphics.font.newFamily` in the Lua SDK.  Variants that
+/// aren't provided fall back to the regular font.
+///
+/// Use with `Graphics::draw_styled_text`, which switches between the fonts using the same markup
+/// as the Lua SDK: text between `*` is bold, text between `_` is italic, and `**` or `__` draw a
+/// literal `*` or `_`.
+#[derive(Clone, Debug)]
+pub struct FontFamily {
+    regular: Font,
+    bold: Option<Font>,
+    italic: Option<Font>,
+}
+
+impl FontFamily {
+    pub fn new(regular: Font) -> Self {
+        Self {
+            regular,
+            bold: None,
+            italic: None,
+        }
+    }
+
+    pub fn with_bold(mut self, bold: Font) -> Self {
+        self.bold = Some(bold);
+        self
+    }
+
+    pub fn with_italic(mut self, italic: Font) -> Self {
+        self.italic = Some(italic);
+        self
+    }
+
+    /// Returns the font for the given variant, or the regular font if that variant wasn't given.
+    pub fn get(&self, variant: FontVariant) -> &Font {
+        match variant {
+            FontVariant::Normal => &self.regular,
+            FontVariant::Bold => self.bold.as_ref().unwrap_or(&self.regular),
+            FontVariant::Italic => self.italic.as_ref().unwrap_or(&self.regular),
+        }
+    }
+
+    pub fn regular(&self) -> &Font {
+        &self.regular
+    }
+}
+
+/// Splits text using the `*bold*` and `_italic_` markup into runs of a single variant.  Bold wins
+/// if a run is marked both bold and italic, since families have no bold-italic member.
+pub(crate) fn styled_runs(text: &str) -> Vec<(FontVariant, String)> {
+    let mut runs = Vec::new();
+    let mut current = String::new();
+    let mut bold = false;
+    let mut italic = false;
+    let mut chars = text.chars().peekable();
+
+    let variant = |bold: bool, italic: bool| {
+        if bold {
+            FontVariant::Bold
+        } else if italic {
+            FontVariant::Italic
+        } else {
+            FontVariant::Normal
+        }
+    };
+
+    while let Some(c) = chars.next() {
+        if c == '*' || c == '_' {
+            if chars.peek() == Some(&c) {
+                chars.next();
+                current.push(c);
+                continue;
+            }
+            if !current.is_empty() {
+                runs.push((variant(bold, italic), core::mem::take(&mut current)));
+            }
+            if c == '*' {
+                bold = !bold;
+            } else {
+                italic = !italic;
+            }
+        } else {
+            current.push(c);
+        }
+    }
+    if !current.is_empty() {
+        runs.push((variant(bold, italic), current));
+    }
+    runs
+}
@@ -0,0 +1,177 @@
+//! Resizable panels drawn from a bitmap cut into a 3x3 grid, like Lua's
+//! `playdate.graphics.nineSlice`.
+//!
+//! The corners are drawn at their natural size, the edges are stretched or tiled along one
+//! axis, and the center is stretched or tiled along both, so one small image can frame a
+//! dialogue box or menu of any size.
+
+use {
+    super::{Bitmap, Graphics, LCDBitmapFlip, LCDColor, LCDSolidColor},
+    crate::{
+        geometry::{ScreenPoint, ScreenRect, ScreenSize},
+        sprite::Sprite,
+    },
+    anyhow::{ensure, Error},
+    euclid::default::Vector2D,
+};
+
+/// How the edges and center of a `NineSlice` fill the space between the corners.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SliceFill {
+    /// Scale the piece to fit.  Smooth gradients and solid areas look best this way.
+    Stretch,
+    /// Repeat the piece at its natural size, cutting off the last copy.  Patterned borders
+    /// keep their pixel detail this way.
+    Tile,
+}
+
+#[derive(Clone, Debug)]
+pub struct NineSlice {
+    // Row by row, top left to bottom right; None where a piece has no area.
+    pieces: [Option<Bitmap>; 9],
+    // Widths of the left, center and right columns, and heights of the top, middle and bottom
+    // rows, in the source bitmap.
+    columns: [i32; 3],
+    rows: [i32; 3],
+    fill: SliceFill,
+}
+
+impl NineSlice {
+    /// Slices `bitmap` around `center`, the part that's stretched or tiled in both directions.
+    /// Everything above, below and to either side of it becomes the edges and corners.
+    pub fn new(bitmap: &Bitmap, center: ScreenRect) -> Result<Self, Error> {
+        let data = bitmap.get_data()?;
+        let bounds = ScreenRect::new(ScreenPoint::zero(), (data.width, data.height).into());
+        ensure!(
+            bounds.contains_rect(&center),
+            "nine-slice center {:?} isn't inside the {}x{} bitmap",
+            center,
+            data.width,
+            data.height
+        );
+        let columns = [
+            center.min_x(),
+            center.size.width,
+            data.width - center.max_x(),
+        ];
+        let rows = [
+            center.min_y(),
+            center.size.height,
+            data.height - center.max_y(),
+        ];
+
+        let graphics = Graphics::get();
+        let mut pieces: [Option<Bitmap>; 9] = Default::default();
+        let mut y = 0;
+        for (row, &height) in rows.iter().enumerate() {
+            let mut x = 0;
+            for (column, &width) in columns.iter().enumerate() {
+                if width > 0 && height > 0 {
+                    let piece = graphics.new_bitmap(
+                        ScreenSize::new(width, height),
+                        LCDColor::Solid(LCDSolidColor::kColorClear),
+                    )?;
+                    graphics.with_context(&piece, || {
+                        bitmap.draw(ScreenPoint::new(-x, -y), LCDBitmapFlip::kBitmapUnflipped)
+                    })?;
+                    pieces[row * 3 + column] = Some(piece);
+                }
+                x += width;
+            }
+            y += height;
+        }
+
+        Ok(Self {
+            pieces,
+            columns,
+            rows,
+            fill: SliceFill::Stretch,
+        })
+    }
+
+    pub fn load(path: &str, center: ScreenRect) -> Result<Self, Error> {
+        let bitmap = Graphics::get().load_bitmap(path)?;
+        Self::new(&bitmap, center)
+    }
+
+    pub fn fill(&self) -> SliceFill {
+        self.fill
+    }
+
+    pub fn set_fill(&mut self, fill: SliceFill) {
+        self.fill = fill;
+    }
+
+    /// The smallest size that fits the corners without overlapping.
+    pub fn min_size(&self) -> ScreenSize {
+        ScreenSize::new(
+            self.columns[0] + self.columns[2],
+            self.rows[0] + self.rows[2],
+        )
+    }
+
+    /// Draws the panel filling `rect`.  Rects smaller than `min_size` get the corners only,
+    /// overlapping if need be.
+    pub fn draw(&self, rect: ScreenRect) -> Result<(), Error> {
+        let stretch_width = rect.size.width - self.columns[0] - self.columns[2];
+        let stretch_height = rect.size.height - self.rows[0] - self.rows[2];
+        let xs = [
+            rect.min_x(),
+            rect.min_x() + self.columns[0],
+            rect.max_x() - self.columns[2],
+        ];
+        let ys = [
+            rect.min_y(),
+            rect.min_y() + self.rows[0],
+            rect.max_y() - self.rows[2],
+        ];
+        let widths = [self.columns[0], stretch_width, self.columns[2]];
+        let heights = [self.rows[0], stretch_height, self.rows[2]];
+
+        for row in 0..3 {
+            for column in 0..3 {
+                let piece = match &self.pieces[row * 3 + column] {
+                    Some(piece) => piece,
+                    None => continue,
+                };
+                let location = ScreenPoint::new(xs[column], ys[row]);
+                let size = ScreenSize::new(widths[column], heights[row]);
+                if size.width <= 0 || size.height <= 0 {
+                    continue;
+                }
+                if row != 1 && column != 1 {
+                    piece.draw(location, LCDBitmapFlip::kBitmapUnflipped)?;
+                    continue;
+                }
+                match self.fill {
+                    SliceFill::Tile => {
+                        piece.tile(location, size, LCDBitmapFlip::kBitmapUnflipped)?
+                    }
+                    SliceFill::Stretch => {
+                        let scale = Vector2D::new(
+                            size.width as f32 / self.columns[column] as f32,
+                            size.height as f32 / self.rows[row] as f32,
+                        );
+                        piece.draw_scaled(location, scale)?
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Draws the panel into a new bitmap of `size`, transparent wherever the source is.
+    pub fn render(&self, size: ScreenSize) -> Result<Bitmap, Error> {
+        let graphics = Graphics::get();
+        let bitmap = graphics.new_bitmap(size, LCDColor::Solid(LCDSolidColor::kColorClear))?;
+        graphics.with_context(&bitmap, || {
+            self.draw(ScreenRect::new(ScreenPoint::zero(), size))
+        })?;
+        Ok(bitmap)
+    }
+
+    /// Renders the panel at `size` and makes it `sprite`'s image.
+    pub fn set_sprite_image(&self, sprite: &mut Sprite, size: ScreenSize) -> Result<(), Error> {
+        sprite.set_image(self.render(size)?, LCDBitmapFlip::kBitmapUnflipped)
+    }
+}
@@ -0,0 +1,452 @@
+//! Particles for dust, sparks, smoke and explosions.
+//!
+//! A `ParticleSystem` keeps its particles in a buffer allocated once, so thousands can be
+//! simulated without allocating each frame.  Particles are plotted straight into the frame
+//! buffer, or into a bitmap such as a sprite's image, rather than each being a sprite.
+//!
+//! ```ignore
+//! let mut sparks = ParticleSystem::new(2000);
+//! sparks.set_gravity(GrVector::new(0.0, 300.0));
+//! sparks.set_fade(true);
+//! let emitter = sparks.add_emitter(
+//!     Emitter::new(point2(200.0, 120.0))
+//!         .with_rate(0.0)
+//!         .with_speed(40.0, 160.0)
+//!         .with_lifetime(300, 900),
+//! );
+//!
+//! // When something explodes:
+//! sparks.burst(emitter, 200);
+//!
+//! // Each frame, after drawing the scene:
+//! sparks.update(delta_ms);
+//! sparks.draw()?;
+//! ```
+
+use {
+    super::{dither::BAYER_4X4, next_random, Bitmap, Graphics, LCDBitmapFlip, LCDSolidColor},
+    crate::geometry::{GrPoint, GrVector, ScreenPoint, ScreenVector, ToScreenPoint},
+    alloc::vec::Vec,
+    anyhow::Error,
+    crankstart_sys::{LCD_COLUMNS, LCD_ROWS, LCD_ROWSIZE},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Particle {
+    pub position: GrPoint,
+    /// In pixels per second.
+    pub velocity: GrVector,
+    pub age_ms: u32,
+    pub lifetime_ms: u32,
+}
+
+impl Particle {
+    /// Returns how much of the particle's life has passed, from 0.0 to 1.0.
+    pub fn life_fraction(&self) -> f32 {
+        if self.lifetime_ms == 0 {
+            1.0
+        } else {
+            (self.age_ms as f32 / self.lifetime_ms as f32).min(1.0)
+        }
+    }
+}
+
+/// How each particle is drawn.
+#[derive(Clone, Debug)]
+pub enum ParticleStyle {
+    /// A single pixel.
+    Pixel,
+    /// A filled square this many pixels across.
+    Rect(u8),
+    /// Bitmaps centered on the particle, stepping through them over its life, e.g. the frames
+    /// of a `BitmapTable`.  The frames set the look, so fading doesn't apply.
+    Bitmaps(Vec<Bitmap>),
+}
+
+/// Spawns particles from a point, continuously or in bursts.
+#[derive(Clone, Debug)]
+pub struct Emitter {
+    position: GrPoint,
+    // Particles per second.
+    rate: f32,
+    // Degrees clockwise from straight up, like the crank.
+    direction: f32,
+    spread: f32,
+    speed: (f32, f32),
+    lifetime_ms: (u32, u32),
+    active: bool,
+    // Fractions of a particle owed from earlier updates.
+    pending: f32,
+}
+
+impl Emitter {
+    /// Creates an emitter at `position` that sprays 60 particles a second in every direction.
+    pub fn new(position: GrPoint) -> Self {
+        Self {
+            position,
+            rate: 60.0,
+            direction: 0.0,
+            spread: 360.0,
+            speed: (20.0, 60.0),
+            lifetime_ms: (500, 1000),
+            active: true,
+            pending: 0.0,
+        }
+    }
+
+    /// Sets how many particles are emitted a second while active; 0 for bursts only.
+    pub fn with_rate(mut self, per_second: f32) -> Self {
+        self.set_rate(per_second);
+        self
+    }
+
+    /// Aims particles `degrees` clockwise from straight up, scattered across `spread` degrees.
+    pub fn with_direction(mut self, degrees: f32, spread: f32) -> Self {
+        self.direction = degrees;
+        self.spread = spread;
+        self
+    }
+
+    /// Sets the range of starting speeds, in pixels per second.
+    pub fn with_speed(mut self, min: f32, max: f32) -> Self {
+        self.speed = (min, max.max(min));
+        self
+    }
+
+    pub fn with_lifetime(mut self, min_ms: u32, max_ms: u32) -> Self {
+        self.lifetime_ms = (min_ms, max_ms.max(min_ms));
+        self
+    }
+
+    pub fn get_position(&self) -> GrPoint {
+        self.position
+    }
+
+    pub fn set_position(&mut self, position: GrPoint) {
+        self.position = position;
+    }
+
+    pub fn set_rate(&mut self, per_second: f32) {
+        self.rate = per_second.max(0.0);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Starts or stops continuous emission.  Bursts work either way.
+    pub fn set_active(&mut self, active: bool) {
+        self.active = active;
+        self.pending = 0.0;
+    }
+
+    fn spawn(&self, seed: &mut u32) -> Particle {
+        let between = |seed: &mut u32, min: f32, max: f32| {
+            min + (max - min) * (next_random(seed) + 1.0) / 2.0
+        };
+        let angle = (self.direction + next_random(seed) * self.spread / 2.0).to_radians();
+        let speed = between(seed, self.speed.0, self.speed.1);
+        let lifetime = between(seed, self.lifetime_ms.0 as f32, self.lifetime_ms.1 as f32);
+        Particle {
+            position: self.position,
+            velocity: GrVector::new(libm::sinf(angle), -libm::cosf(angle)) * speed,
+            age_ms: 0,
+            lifetime_ms: lifetime as u32,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ParticleSystem {
+    particles: Vec<Particle>,
+    capacity: usize,
+    emitters: Vec<Option<Emitter>>,
+    style: ParticleStyle,
+    color: LCDSolidColor,
+    // In pixels per second per second.
+    gravity: GrVector,
+    // Fraction of velocity lost each second.
+    drag: f32,
+    fade: bool,
+    // State of the xorshift generator for emission.
+    seed: u32,
+}
+
+impl ParticleSystem {
+    /// Creates a system for up to `capacity` particles, drawn as black pixels.  Particles
+    /// emitted while it's full are dropped.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            particles: Vec::with_capacity(capacity),
+            capacity,
+            emitters: Vec::new(),
+            style: ParticleStyle::Pixel,
+            color: LCDSolidColor::kColorBlack,
+            gravity: GrVector::zero(),
+            drag: 0.0,
+            fade: false,
+            seed: 0x9e37_79b9,
+        }
+    }
+
+    pub fn set_style(&mut self, style: ParticleStyle) {
+        self.style = style;
+    }
+
+    /// Sets the color of pixel and rect particles.  XOR inverts whatever they're drawn over.
+    pub fn set_color(&mut self, color: LCDSolidColor) {
+        self.color = color;
+    }
+
+    /// Sets the acceleration applied to every particle, in pixels per second per second.
+    pub fn set_gravity(&mut self, gravity: GrVector) {
+        self.gravity = gravity;
+    }
+
+    /// Sets the fraction of its speed a particle loses each second, from 0.0 to 1.0.
+    pub fn set_drag(&mut self, drag: f32) {
+        self.drag = drag.clamp(0.0, 1.0);
+    }
+
+    /// Makes pixel and rect particles thin out through a dither pattern as they age.
+    pub fn set_fade(&mut self, fade: bool) {
+        self.fade = fade;
+    }
+
+    /// Adds an emitter and returns its index, for `emitter_mut`, `burst` and
+    /// `remove_emitter`.
+    pub fn add_emitter(&mut self, emitter: Emitter) -> usize {
+        if let Some(index) = self.emitters.iter().position(Option::is_none) {
+            self.emitters[index] = Some(emitter);
+            index
+        } else {
+            self.emitters.push(Some(emitter));
+            self.emitters.len() - 1
+        }
+    }
+
+    pub fn emitter_mut(&mut self, index: usize) -> Option<&mut Emitter> {
+        self.emitters.get_mut(index).and_then(Option::as_mut)
+    }
+
+    /// Removes an emitter, leaving the particles it emitted to live out their lives.
+    pub fn remove_emitter(&mut self, index: usize) -> Option<Emitter> {
+        self.emitters.get_mut(index).and_then(Option::take)
+    }
+
+    /// Emits `count` particles at once from the emitter at `index`.
+    pub fn burst(&mut self, index: usize, count: usize) {
+        if let Some(Some(emitter)) = self.emitters.get(index) {
+            for _ in 0..count {
+                let particle = emitter.spawn(&mut self.seed);
+                if !push(&mut self.particles, self.capacity, particle) {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Adds a particle directly.  Returns false if the system is full.
+    pub fn emit(&mut self, particle: Particle) -> bool {
+        push(&mut self.particles, self.capacity, particle)
+    }
+
+    pub fn particles(&self) -> &[Particle] {
+        &self.particles
+    }
+
+    pub fn len(&self) -> usize {
+        self.particles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.particles.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.particles.clear();
+    }
+
+    /// Emits from active emitters and moves and ages every particle by `delta_ms`, removing
+    /// those that have lived out their lifetime.
+    pub fn update(&mut self, delta_ms: usize) {
+        let seconds = delta_ms as f32 / 1000.0;
+        let delta_ms = delta_ms as u32;
+
+        let mut index = 0;
+        while index < self.particles.len() {
+            let particle = &mut self.particles[index];
+            particle.age_ms = particle.age_ms.saturating_add(delta_ms);
+            if particle.age_ms >= particle.lifetime_ms {
+                self.particles.swap_remove(index);
+                continue;
+            }
+            index += 1;
+        }
+
+        let keep = if self.drag > 0.0 {
+            libm::powf(1.0 - self.drag, seconds)
+        } else {
+            1.0
+        };
+        for particle in &mut self.particles {
+            particle.velocity = (particle.velocity + self.gravity * seconds) * keep;
+            particle.position += particle.velocity * seconds;
+        }
+
+        for emitter in self.emitters.iter_mut().flatten() {
+            if !emitter.active {
+                continue;
+            }
+            emitter.pending += emitter.rate * seconds;
+            while emitter.pending >= 1.0 {
+                emitter.pending -= 1.0;
+                let particle = emitter.spawn(&mut self.seed);
+                if !push(&mut self.particles, self.capacity, particle) {
+                    emitter.pending = 0.0;
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Draws the particles into the frame buffer, honoring the draw offset.
+    pub fn draw(&self) -> Result<(), Error> {
+        let graphics = Graphics::get();
+        let offset = graphics.get_draw_offset();
+        if let ParticleStyle::Bitmaps(frames) = &self.style {
+            return self.draw_bitmaps(frames, ScreenVector::zero());
+        }
+
+        let mut canvas = Canvas {
+            width: LCD_COLUMNS as i32,
+            height: LCD_ROWS as i32,
+            rowbytes: LCD_ROWSIZE as usize,
+            data: graphics.get_frame()?,
+            mask: None,
+        };
+        if let Some(rows) = self.plot(&mut canvas, offset) {
+            graphics.mark_updated_rows(rows.0..=rows.1)?;
+        }
+        Ok(())
+    }
+
+    /// Draws the particles into `bitmap`, e.g. a sprite's image.  `origin` is the point in
+    /// the particles' coordinates that lands at the bitmap's top left.
+    pub fn draw_into(&self, bitmap: &Bitmap, origin: ScreenPoint) -> Result<(), Error> {
+        let offset = -origin.to_vector();
+        if let ParticleStyle::Bitmaps(frames) = &self.style {
+            let graphics = Graphics::get();
+            let draw_offset = graphics.get_draw_offset();
+            graphics.set_draw_offset(ScreenVector::zero())?;
+            let drawn = graphics.with_context(bitmap, || self.draw_bitmaps(frames, offset));
+            graphics.set_draw_offset(draw_offset)?;
+            return drawn;
+        }
+
+        let mut inner = bitmap.inner.borrow_mut();
+        let pixels = inner.get_pixels_mut()?;
+        let mut canvas = Canvas {
+            width: pixels.width as i32,
+            height: pixels.height as i32,
+            rowbytes: pixels.rowbytes,
+            data: pixels.data,
+            mask: pixels.mask,
+        };
+        self.plot(&mut canvas, offset);
+        Ok(())
+    }
+
+    fn draw_bitmaps(&self, frames: &[Bitmap], offset: ScreenVector) -> Result<(), Error> {
+        if frames.is_empty() {
+            return Ok(());
+        }
+        let mut half_sizes = Vec::with_capacity(frames.len());
+        for frame in frames {
+            let data = frame.get_data()?;
+            half_sizes.push(ScreenVector::new(data.width / 2, data.height / 2));
+        }
+        for particle in &self.particles {
+            let frame =
+                ((particle.life_fraction() * frames.len() as f32) as usize).min(frames.len() - 1);
+            let location = particle.position.to_screen_point() + offset - half_sizes[frame];
+            frames[frame].draw(location, LCDBitmapFlip::kBitmapUnflipped)?;
+        }
+        Ok(())
+    }
+
+    /// Plots pixel or rect particles, returning the first and last rows touched.
+    fn plot(&self, canvas: &mut Canvas, offset: ScreenVector) -> Option<(i32, i32)> {
+        let size = match self.style {
+            ParticleStyle::Rect(size) => size.max(1) as i32,
+            _ => 1,
+        };
+        let mut rows: Option<(i32, i32)> = None;
+        for particle in &self.particles {
+            let corner = particle.position.to_screen_point() + offset
+                - ScreenVector::new(size / 2, size / 2);
+            let min_y = corner.y.max(0);
+            let max_y = (corner.y + size).min(canvas.height) - 1;
+            if min_y > max_y || corner.x + size <= 0 || corner.x >= canvas.width {
+                continue;
+            }
+            // Seventeen levels, from fully drawn down to nothing, like `pattern::grey`.
+            let level = if self.fade {
+                libm::ceilf((1.0 - particle.life_fraction()) * 16.0) as u8
+            } else {
+                16
+            };
+            for y in min_y..=max_y {
+                for x in corner.x.max(0)..(corner.x + size).min(canvas.width) {
+                    if level > BAYER_4X4[y as usize % 4][x as usize % 4] {
+                        canvas.set(x as usize, y as usize, self.color);
+                    }
+                }
+            }
+            rows = Some(match rows {
+                Some((first, last)) => (first.min(min_y), last.max(max_y)),
+                None => (min_y, max_y),
+            });
+        }
+        rows
+    }
+}
+
+/// Adds `particle` unless the buffer is full, so it never reallocates.
+fn push(particles: &mut Vec<Particle>, capacity: usize, particle: Particle) -> bool {
+    if particles.len() >= capacity {
+        return false;
+    }
+    particles.push(particle);
+    true
+}
+
+/// One bit per pixel rows to plot into, most significant bit first.
+struct Canvas<'a> {
+    width: i32,
+    height: i32,
+    rowbytes: usize,
+    data: &'a mut [u8],
+    mask: Option<&'a mut [u8]>,
+}
+
+impl Canvas<'_> {
+    fn set(&mut self, x: usize, y: usize, color: LCDSolidColor) {
+        let index = y * self.rowbytes + x / 8;
+        let bit = 0x80 >> (x % 8);
+        match color {
+            LCDSolidColor::kColorBlack => self.data[index] &= !bit,
+            LCDSolidColor::kColorWhite => self.data[index] |= bit,
+            LCDSolidColor::kColorXOR => self.data[index] ^= bit,
+            LCDSolidColor::kColorClear => {
+                if let Some(mask) = &mut self.mask {
+                    mask[index] &= !bit;
+                }
+                return;
+            }
+        }
+        if let Some(mask) = &mut self.mask {
+            mask[index] |= bit;
+        }
+    }
+}
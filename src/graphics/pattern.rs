@@ -0,0 +1,153 @@
+//! 8x8 patterns for use with `LCDColor::Pattern`.
+//!
+//! An `LCDPattern` is eight rows of pixels, most significant bit leftmost and set bits white,
+//! followed by eight rows of mask with set bits opaque.  The patterns here are fully opaque.
+//!
+//! ```ignore
+//! graphics.fill_rect(rect, LCDColor::Pattern(pattern::CHECKERBOARD))?;
+//! graphics.fill_rect(shadow, LCDColor::grey(4))?;
+//! ```
+
+use {
+    super::{dither::BAYER_4X4, LCDColor, LCDPattern},
+    alloc::vec::Vec,
+    euclid::default::Vector2D,
+};
+
+/// Number of distinct levels `grey` can produce, counting black and white.
+pub const GREY_LEVELS: u8 = 17;
+
+/// Builds an opaque pattern from eight rows of pixels.
+pub const fn opaque(rows: [u8; 8]) -> LCDPattern {
+    let mut pattern = [0xff; 16];
+    let mut y = 0;
+    while y < 8 {
+        pattern[y] = rows[y];
+        y += 1;
+    }
+    pattern
+}
+
+/// One of 17 evenly spaced greys made with a 4x4 Bayer matrix; 0 is black and 16 is white.
+/// Levels above 16 are treated as white.  Each level adds pixels to the one below it, so
+/// stepping through the levels makes a smooth fade.
+pub const fn grey(level: u8) -> LCDPattern {
+    let mut rows = [0u8; 8];
+    let mut y = 0;
+    while y < 8 {
+        let mut x = 0;
+        while x < 8 {
+            if level > BAYER_4X4[y % 4][x % 4] {
+                rows[y] |= 0x80 >> x;
+            }
+            x += 1;
+        }
+        y += 1;
+    }
+    opaque(rows)
+}
+
+/// The grey `t` of the way from level `start` to level `end`, with `t` clamped to 0.0..=1.0.
+pub fn grey_between(start: u8, end: u8, t: f32) -> LCDPattern {
+    let t = t.clamp(0.0, 1.0);
+    let level = start as f32 + (end as f32 - start as f32) * t;
+    grey((level + 0.5) as u8)
+}
+
+pub const BLACK: LCDPattern = grey(0);
+pub const WHITE: LCDPattern = grey(16);
+pub const CHECKERBOARD: LCDPattern = opaque([0xaa, 0x55, 0xaa, 0x55, 0xaa, 0x55, 0xaa, 0x55]);
+/// Alternating black and white rows.
+pub const HORIZONTAL_STRIPES: LCDPattern = opaque([0x00, 0xff, 0x00, 0xff, 0x00, 0xff, 0x00, 0xff]);
+/// Alternating white and black columns.
+pub const VERTICAL_STRIPES: LCDPattern = opaque([0xaa; 8]);
+/// Black lines running from top left to bottom right on white, four pixels apart.
+pub const DIAGONAL_STRIPES: LCDPattern = opaque([0x77, 0xbb, 0xdd, 0xee, 0x77, 0xbb, 0xdd, 0xee]);
+/// A grid of black lines on white, four pixels apart.
+pub const CROSSHATCH: LCDPattern = opaque([0x00, 0x77, 0x77, 0x77, 0x00, 0x77, 0x77, 0x77]);
+/// Black lines in both diagonal directions on white, four pixels apart.
+pub const DIAGONAL_CROSSHATCH: LCDPattern =
+    opaque([0x66, 0x99, 0x99, 0x66, 0x66, 0x99, 0x99, 0x66]);
+
+/// Swaps black and white, leaving the mask alone.
+pub fn inverted(pattern: &LCDPattern) -> LCDPattern {
+    let mut out = *pattern;
+    out[..8].iter_mut().for_each(|row| *row = !*row);
+    out
+}
+
+/// Moves the pattern `dx` pixels right and `dy` pixels down, wrapping around.
+pub fn shifted(pattern: &LCDPattern, dx: i32, dy: i32) -> LCDPattern {
+    let mut out = [0u8; 16];
+    let dx = dx.rem_euclid(8) as u32;
+    for y in 0..8 {
+        let from = (y as i32 - dy).rem_euclid(8) as usize;
+        out[y] = pattern[from].rotate_right(dx);
+        out[y + 8] = pattern[from + 8].rotate_right(dx);
+    }
+    out
+}
+
+/// A pattern that cycles through frames at a fixed rate, e.g. for shimmering water.
+#[derive(Clone, Debug)]
+pub struct AnimatedPattern {
+    frames: Vec<LCDPattern>,
+    frame_duration_ms: usize,
+}
+
+impl AnimatedPattern {
+    pub fn new(frames: Vec<LCDPattern>, frame_duration_ms: usize) -> Self {
+        Self {
+            frames,
+            frame_duration_ms,
+        }
+    }
+
+    /// Steps through grey levels `start` to `end` and back again, for pulsing shading.
+    pub fn pulse(start: u8, end: u8, frame_duration_ms: usize) -> Self {
+        let (low, high) = (start.min(end), start.max(end));
+        let mut frames: Vec<LCDPattern> = (low..=high).map(grey).collect();
+        frames.extend((low.saturating_add(1)..high).rev().map(grey));
+        Self::new(frames, frame_duration_ms)
+    }
+
+    /// Returns the frame to show `elapsed_ms` after the animation started.
+    pub fn pattern_at(&self, elapsed_ms: usize) -> LCDPattern {
+        if self.frames.is_empty() {
+            return BLACK;
+        }
+        let index = elapsed_ms / self.frame_duration_ms.max(1) % self.frames.len();
+        self.frames[index]
+    }
+
+    pub fn color_at(&self, elapsed_ms: usize) -> LCDColor {
+        LCDColor::Pattern(self.pattern_at(elapsed_ms))
+    }
+}
+
+/// A pattern that slides across the screen at a fixed velocity, in pixels per second.
+#[derive(Clone, Debug)]
+pub struct ScrollingPattern {
+    pattern: LCDPattern,
+    velocity: Vector2D<f32>,
+}
+
+impl ScrollingPattern {
+    pub fn new(pattern: LCDPattern, velocity: Vector2D<f32>) -> Self {
+        Self { pattern, velocity }
+    }
+
+    /// Returns the pattern as it should appear `elapsed_ms` after scrolling started.
+    pub fn pattern_at(&self, elapsed_ms: usize) -> LCDPattern {
+        let seconds = elapsed_ms as f32 / 1000.0;
+        // Only the position within one 8x8 tile matters, so wrap before converting to keep
+        // precision over long run times.
+        let dx = (self.velocity.x * seconds) % 8.0;
+        let dy = (self.velocity.y * seconds) % 8.0;
+        shifted(&self.pattern, dx as i32, dy as i32)
+    }
+
+    pub fn color_at(&self, elapsed_ms: usize) -> LCDColor {
+        LCDColor::Pattern(self.pattern_at(elapsed_ms))
+    }
+}
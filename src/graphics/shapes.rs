@@ -0,0 +1,429 @@
+//! Outlined and filled shapes built on the system's line, polygon and ellipse drawing.
+//!
+//! Points are `GrPoint`s so shapes computed with fractional coordinates can be drawn without
+//! rounding each vertex by hand.  Angles are in degrees, clockwise from twelve o'clock, as for
+//! `draw_ellipse`.
+
+use {
+    super::{Graphics, LCDColor, LCDLineCapStyle, LCDPolygonFillRule},
+    crate::{
+        geometry::{GrPoint, GrVector, ScreenPoint, ScreenRect, ScreenSize, ToScreenPoint},
+        pd_func_caller,
+    },
+    alloc::vec::Vec,
+    anyhow::Error,
+};
+
+/// How the corners between the segments of a thick polyline are drawn.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineJoin {
+    /// Segments are extended to a point, unless that point would be more than `MITER_LIMIT`
+    /// half-widths from the corner, in which case the corner is beveled.
+    Miter,
+    /// The corner is cut off square.
+    Bevel,
+    /// The corner is rounded off with a circle the width of the line.
+    Round,
+}
+
+/// The longest miter drawn, in half line widths; sharper corners are beveled.  This is the
+/// same default SVG uses.
+pub const MITER_LIMIT: f32 = 4.0;
+
+// Corners flatter than this are drawn without a join.
+const MIN_TURN: f32 = 1e-3;
+
+impl Graphics {
+    /// Draws a line through `points`, using the current line cap style at the two ends and
+    /// `join` at each corner.
+    pub fn draw_polyline(
+        &self,
+        points: &[GrPoint],
+        width: i32,
+        join: LineJoin,
+        color: LCDColor,
+    ) -> Result<(), Error> {
+        self.stroke(points, false, width, join, color)
+    }
+
+    /// Draws the outline of a closed polygon, joining the last point back to the first.
+    pub fn draw_polygon(
+        &self,
+        points: &[GrPoint],
+        width: i32,
+        join: LineJoin,
+        color: LCDColor,
+    ) -> Result<(), Error> {
+        self.stroke(points, true, width, join, color)
+    }
+
+    /// Draws part of a circle from `start_angle` to `end_angle`.
+    pub fn draw_arc(
+        &self,
+        center: GrPoint,
+        radius: f32,
+        start_angle: f32,
+        end_angle: f32,
+        width: i32,
+        color: LCDColor,
+    ) -> Result<(), Error> {
+        let (origin, size) = circle_bounds(center, radius);
+        self.draw_ellipse(origin, size, width, start_angle, end_angle, color)
+    }
+
+    /// Draws the outline of a circle, with the line lying inside `radius`.
+    pub fn draw_circle_at_center(
+        &self,
+        center: GrPoint,
+        radius: f32,
+        width: i32,
+        color: LCDColor,
+    ) -> Result<(), Error> {
+        self.draw_arc(center, radius, 0.0, 0.0, width, color)
+    }
+
+    pub fn fill_circle_at_center(
+        &self,
+        center: GrPoint,
+        radius: f32,
+        color: LCDColor,
+    ) -> Result<(), Error> {
+        let (origin, size) = circle_bounds(center, radius);
+        pd_func_caller!(
+            (*self.0).fillEllipse,
+            origin.x,
+            origin.y,
+            size.width,
+            size.height,
+            0.0,
+            0.0,
+            usize::from(&color),
+        )
+    }
+
+    /// Draws the outline of `rect` with its corners rounded to `radius`, with the line lying
+    /// inside the rect.
+    pub fn draw_round_rect(
+        &self,
+        rect: ScreenRect,
+        radius: i32,
+        width: i32,
+        color: LCDColor,
+    ) -> Result<(), Error> {
+        let radius = clamp_radius(rect, radius);
+        let (x, y) = (rect.origin.x, rect.origin.y);
+        let (w, h) = (rect.size.width, rect.size.height);
+        let straight_w = w - 2 * radius;
+        let straight_h = h - 2 * radius;
+        for edge in [
+            ScreenRect::new((x + radius, y).into(), (straight_w, width).into()),
+            ScreenRect::new(
+                (x + radius, y + h - width).into(),
+                (straight_w, width).into(),
+            ),
+            ScreenRect::new((x, y + radius).into(), (width, straight_h).into()),
+            ScreenRect::new(
+                (x + w - width, y + radius).into(),
+                (width, straight_h).into(),
+            ),
+        ] {
+            if !edge.is_empty() {
+                self.fill_rect(edge, color.clone())?;
+            }
+        }
+        if radius == 0 {
+            // The edges already meet at square corners.
+            return Ok(());
+        }
+        let corner = ScreenSize::new(2 * radius, 2 * radius);
+        for (origin, start_angle) in round_rect_corners(rect, radius) {
+            self.draw_ellipse(
+                origin,
+                corner,
+                width,
+                start_angle,
+                start_angle + 90.0,
+                color.clone(),
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn fill_round_rect(
+        &self,
+        rect: ScreenRect,
+        radius: i32,
+        color: LCDColor,
+    ) -> Result<(), Error> {
+        let radius = clamp_radius(rect, radius);
+        if radius == 0 {
+            return self.fill_rect(rect, color);
+        }
+        // A cross of two rects covers everything except the corners.
+        let across = rect.inflate(0, -radius);
+        let down = rect.inflate(-radius, 0);
+        for part in [across, down] {
+            if !part.is_empty() {
+                self.fill_rect(part, color.clone())?;
+            }
+        }
+        for (origin, start_angle) in round_rect_corners(rect, radius) {
+            pd_func_caller!(
+                (*self.0).fillEllipse,
+                origin.x,
+                origin.y,
+                2 * radius,
+                2 * radius,
+                start_angle,
+                start_angle + 90.0,
+                usize::from(&color),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Draws a quadratic Bézier curve from `start` to `end`, bending toward `control`.
+    pub fn draw_quad_bezier(
+        &self,
+        start: GrPoint,
+        control: GrPoint,
+        end: GrPoint,
+        width: i32,
+        color: LCDColor,
+    ) -> Result<(), Error> {
+        let steps = curve_steps(&[start, control, end]);
+        let points: Vec<GrPoint> = (0..=steps)
+            .map(|step| {
+                let t = step as f32 / steps as f32;
+                let a = start.lerp(control, t);
+                let b = control.lerp(end, t);
+                a.lerp(b, t)
+            })
+            .collect();
+        self.stroke(&points, false, width, LineJoin::Bevel, color)
+    }
+
+    /// Draws a cubic Bézier curve from `start` to `end`, leaving `start` toward `control1` and
+    /// arriving at `end` from `control2`.
+    pub fn draw_cubic_bezier(
+        &self,
+        start: GrPoint,
+        control1: GrPoint,
+        control2: GrPoint,
+        end: GrPoint,
+        width: i32,
+        color: LCDColor,
+    ) -> Result<(), Error> {
+        let steps = curve_steps(&[start, control1, control2, end]);
+        let points: Vec<GrPoint> = (0..=steps)
+            .map(|step| {
+                let t = step as f32 / steps as f32;
+                let a = start.lerp(control1, t);
+                let b = control1.lerp(control2, t);
+                let c = control2.lerp(end, t);
+                let ab = a.lerp(b, t);
+                let bc = b.lerp(c, t);
+                ab.lerp(bc, t)
+            })
+            .collect();
+        self.stroke(&points, false, width, LineJoin::Bevel, color)
+    }
+
+    fn stroke(
+        &self,
+        points: &[GrPoint],
+        closed: bool,
+        width: i32,
+        join: LineJoin,
+        color: LCDColor,
+    ) -> Result<(), Error> {
+        let mut points: Vec<GrPoint> =
+            points
+                .iter()
+                .copied()
+                .fold(Vec::with_capacity(points.len()), |mut points, point| {
+                    if points.last() != Some(&point) {
+                        points.push(point);
+                    }
+                    points
+                });
+        if closed && points.len() > 1 && points.first() == points.last() {
+            points.pop();
+        }
+        // Two or fewer distinct points don't enclose anything, so draw them as an open line.
+        let closed = closed && points.len() >= 3;
+        match points.len() {
+            0 => return Ok(()),
+            1 => return self.draw_cap(points[0], GrVector::new(1.0, 0.0), width, color, true),
+            _ => {}
+        }
+        let segments = if closed {
+            points.len()
+        } else {
+            points.len() - 1
+        };
+
+        // Draw the segments with flat ends so they meet cleanly, then add the joins and caps.
+        let cap_style = self.get_line_cap_style();
+        if cap_style != LCDLineCapStyle::kLineCapStyleButt {
+            self.set_line_cap_style(LCDLineCapStyle::kLineCapStyleButt)?;
+        }
+        let drawn = (0..segments).try_for_each(|i| {
+            let (a, b) = (points[i], points[(i + 1) % points.len()]);
+            self.draw_line(
+                a.to_screen_point(),
+                b.to_screen_point(),
+                width,
+                color.clone(),
+            )
+        });
+        if cap_style != LCDLineCapStyle::kLineCapStyleButt {
+            self.set_line_cap_style(cap_style)?;
+        }
+        drawn?;
+
+        if width <= 1 {
+            return Ok(());
+        }
+        let half_width = width as f32 / 2.0;
+        let corners = if closed {
+            0..points.len()
+        } else {
+            1..points.len() - 1
+        };
+        for i in corners {
+            let before = points[(i + points.len() - 1) % points.len()];
+            let after = points[(i + 1) % points.len()];
+            self.draw_join(before, points[i], after, half_width, join, color.clone())?;
+        }
+        if !closed {
+            let last = points.len() - 1;
+            if let Some(start_direction) = (points[0] - points[1]).try_normalize() {
+                self.draw_cap(points[0], start_direction, width, color.clone(), false)?;
+            }
+            if let Some(end_direction) = (points[last] - points[last - 1]).try_normalize() {
+                self.draw_cap(points[last], end_direction, width, color, false)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn draw_join(
+        &self,
+        before: GrPoint,
+        corner: GrPoint,
+        after: GrPoint,
+        half_width: f32,
+        join: LineJoin,
+        color: LCDColor,
+    ) -> Result<(), Error> {
+        let (incoming, outgoing) = match (
+            (corner - before).try_normalize(),
+            (after - corner).try_normalize(),
+        ) {
+            (Some(incoming), Some(outgoing)) => (incoming, outgoing),
+            _ => return Ok(()),
+        };
+        let turn = incoming.cross(outgoing);
+        if turn.abs() < MIN_TURN && incoming.dot(outgoing) > 0.0 {
+            return Ok(());
+        }
+        if join == LineJoin::Round {
+            return self.fill_circle_at_center(corner, half_width, color);
+        }
+
+        // The gap to fill is on the outside of the turn.
+        let side = if turn > 0.0 { -half_width } else { half_width };
+        let outer_in = corner + normal(incoming) * side;
+        let outer_out = corner + normal(outgoing) * side;
+        let bisector = normal(incoming) + normal(outgoing);
+        let cos_half_angle = bisector.length() / 2.0;
+        let miter_length = 1.0 / cos_half_angle.max(f32::EPSILON);
+        if join == LineJoin::Miter && miter_length <= MITER_LIMIT {
+            let miter = corner + bisector.normalize() * side * miter_length;
+            self.fill_polygon(
+                &[corner, outer_in, miter, outer_out],
+                color,
+                LCDPolygonFillRule::kPolygonFillNonZero,
+            )
+        } else {
+            self.fill_polygon(
+                &[corner, outer_in, outer_out],
+                color,
+                LCDPolygonFillRule::kPolygonFillNonZero,
+            )
+        }
+    }
+
+    /// Draws the current line cap style at `end`, facing `direction`.  A lone point is drawn
+    /// as a dot, even with flat caps.
+    fn draw_cap(
+        &self,
+        end: GrPoint,
+        direction: GrVector,
+        width: i32,
+        color: LCDColor,
+        dot: bool,
+    ) -> Result<(), Error> {
+        let half_width = width as f32 / 2.0;
+        match self.get_line_cap_style() {
+            LCDLineCapStyle::kLineCapStyleRound if width > 1 => {
+                self.fill_circle_at_center(end, half_width, color)
+            }
+            LCDLineCapStyle::kLineCapStyleButt if !dot => Ok(()),
+            _ => {
+                let along = direction * half_width;
+                let across = normal(direction) * half_width;
+                let back = if dot { end - along } else { end };
+                self.fill_polygon(
+                    &[
+                        back + across,
+                        end + along + across,
+                        end + along - across,
+                        back - across,
+                    ],
+                    color,
+                    LCDPolygonFillRule::kPolygonFillNonZero,
+                )
+            }
+        }
+    }
+}
+
+fn normal(direction: GrVector) -> GrVector {
+    GrVector::new(-direction.y, direction.x)
+}
+
+fn circle_bounds(center: GrPoint, radius: f32) -> (ScreenPoint, ScreenSize) {
+    let origin = GrPoint::new(center.x - radius, center.y - radius).to_screen_point();
+    let diameter = libm::roundf(radius * 2.0) as i32;
+    (origin, ScreenSize::new(diameter, diameter))
+}
+
+fn clamp_radius(rect: ScreenRect, radius: i32) -> i32 {
+    radius.clamp(0, rect.size.width.min(rect.size.height) / 2)
+}
+
+/// The origin of the bounding box of each corner's circle, with the angle its arc starts at.
+fn round_rect_corners(rect: ScreenRect, radius: i32) -> [(ScreenPoint, f32); 4] {
+    let diameter = 2 * radius;
+    let (left, top) = (rect.min_x(), rect.min_y());
+    let right = rect.max_x() - diameter;
+    let bottom = rect.max_y() - diameter;
+    [
+        ((left, top).into(), 270.0),
+        ((right, top).into(), 0.0),
+        ((right, bottom).into(), 90.0),
+        ((left, bottom).into(), 180.0),
+    ]
+}
+
+/// Enough line segments that each is about four pixels long, going by the length of the
+/// control polygon, which is never shorter than the curve.
+fn curve_steps(points: &[GrPoint]) -> usize {
+    let length: f32 = points
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]).length())
+        .sum();
+    ((length / 4.0) as usize).clamp(1, 64)
+}
@@ -0,0 +1,310 @@
+//! Grids of tiles drawn from a `BitmapTable`, like Lua's `playdate.graphics.tilemap`.
+//!
+//! A `Tilemap` can be drawn straight to the framebuffer each frame, or attached to a sprite so
+//! the sprite system only redraws the tiles that changed.  For the latter, forward the
+//! sprite's `Game::draw_sprite` call to `Tilemap::draw_sprite`:
+//!
+//! ```ignore
+//! fn draw_sprite(
+//!     &self,
+//!     sprite: &Sprite,
+//!     bounds: &PDRect,
+//!     draw_rect: &PDRect,
+//!     playdate: &Playdate,
+//! ) -> Result<(), Error> {
+//!     self.tilemap.draw_sprite(bounds, draw_rect)
+//! }
+//! ```
+
+use {
+    super::{AnimationClip, Bitmap, BitmapTable, Graphics, LCDBitmapFlip, LCDRect, PDRect},
+    crate::{
+        geometry::{ScreenPoint, ScreenRect, ScreenSize},
+        sprite::{Sprite, SpriteManager},
+    },
+    alloc::{vec, vec::Vec},
+    anyhow::{ensure, Error},
+    hashbrown::HashMap,
+};
+
+/// One cell of a `Tilemap`: an index into its `BitmapTable` and how to flip the bitmap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tile {
+    pub index: usize,
+    pub flip: LCDBitmapFlip,
+}
+
+impl Tile {
+    pub fn new(index: usize) -> Self {
+        Self::flipped(index, LCDBitmapFlip::kBitmapUnflipped)
+    }
+
+    pub fn flipped(index: usize, flip: LCDBitmapFlip) -> Self {
+        Self { index, flip }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Tilemap {
+    table: BitmapTable,
+    tile_size: ScreenSize,
+    width: usize,
+    height: usize,
+    // Row by row; None for empty cells.
+    tiles: Vec<Option<Tile>>,
+    // Tiles with one of these indexes show the clip's current frame instead.
+    animations: HashMap<usize, AnimationClip>,
+    elapsed_ms: usize,
+    sprite: Option<Sprite>,
+}
+
+impl Tilemap {
+    /// Creates an empty map `width` by `height` tiles.  Tiles are the size of the table's
+    /// first bitmap.  Turns on caching for `table`, since tiles are looked up on every draw.
+    pub fn new(table: BitmapTable, width: usize, height: usize) -> Result<Self, Error> {
+        ensure!(
+            table.len()? > 0,
+            "tilemap needs a table with at least one bitmap"
+        );
+        let data = table.get_bitmap(0)?.get_data()?;
+        table.set_caching(true);
+        Ok(Self {
+            table,
+            tile_size: ScreenSize::new(data.width, data.height),
+            width,
+            height,
+            tiles: vec![None; width * height],
+            animations: HashMap::new(),
+            elapsed_ms: 0,
+            sprite: None,
+        })
+    }
+
+    pub fn get_table(&self) -> &BitmapTable {
+        &self.table
+    }
+
+    /// Returns the size of the map in tiles.
+    pub fn get_size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    pub fn get_tile_size(&self) -> ScreenSize {
+        self.tile_size
+    }
+
+    /// Returns the size of the map in pixels.
+    pub fn get_pixel_size(&self) -> ScreenSize {
+        ScreenSize::new(
+            self.width as i32 * self.tile_size.width,
+            self.height as i32 * self.tile_size.height,
+        )
+    }
+
+    pub fn get_tile(&self, x: usize, y: usize) -> Option<Tile> {
+        if x < self.width && y < self.height {
+            self.tiles[y * self.width + x]
+        } else {
+            None
+        }
+    }
+
+    pub fn set_tile(&mut self, x: usize, y: usize, tile: Option<Tile>) -> Result<(), Error> {
+        ensure!(
+            x < self.width && y < self.height,
+            "tile ({}, {}) is outside the {}x{} tilemap",
+            x,
+            y,
+            self.width,
+            self.height
+        );
+        let cell = &mut self.tiles[y * self.width + x];
+        if *cell != tile {
+            *cell = tile;
+            self.mark_tile_dirty(x, y)?;
+        }
+        Ok(())
+    }
+
+    /// Replaces every tile, row by row.
+    pub fn set_tiles(&mut self, tiles: &[Option<Tile>]) -> Result<(), Error> {
+        ensure!(
+            tiles.len() == self.tiles.len(),
+            "expected {} tiles for a {}x{} tilemap, got {}",
+            self.tiles.len(),
+            self.width,
+            self.height,
+            tiles.len()
+        );
+        self.tiles.copy_from_slice(tiles);
+        self.mark_dirty()
+    }
+
+    /// Animates every tile with table index `index` through `clip`, timed by `update`.
+    pub fn set_animation(&mut self, index: usize, clip: AnimationClip) -> Result<(), Error> {
+        self.animations.insert(index, clip);
+        self.mark_index_dirty(index)
+    }
+
+    pub fn remove_animation(&mut self, index: usize) -> Result<(), Error> {
+        if self.animations.remove(&index).is_some() {
+            self.mark_index_dirty(index)?;
+        }
+        Ok(())
+    }
+
+    /// Advances animated tiles by `delta_ms`, marking any whose frame changed for redraw.
+    pub fn update(&mut self, delta_ms: usize) -> Result<(), Error> {
+        let before = self.elapsed_ms;
+        self.elapsed_ms += delta_ms;
+        let changed: Vec<usize> = self
+            .animations
+            .iter()
+            .filter(|(_, clip)| clip.frame_at(before) != clip.frame_at(self.elapsed_ms))
+            .map(|(&index, _)| index)
+            .collect();
+        for index in changed {
+            self.mark_index_dirty(index)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the column and row of the tile under `point`, given in pixels from the map's
+    /// top left corner.
+    pub fn tile_at_point(&self, point: ScreenPoint) -> Option<(usize, usize)> {
+        if point.x < 0 || point.y < 0 {
+            return None;
+        }
+        let x = (point.x / self.tile_size.width) as usize;
+        let y = (point.y / self.tile_size.height) as usize;
+        (x < self.width && y < self.height).then_some((x, y))
+    }
+
+    /// Draws the map with its top left corner at `location`, skipping tiles that fall outside
+    /// the screen after the current draw offset.
+    pub fn draw(&self, location: ScreenPoint) -> Result<(), Error> {
+        let offset = Graphics::get().get_draw_offset();
+        let screen = ScreenRect::new(
+            ScreenPoint::new(-offset.x, -offset.y),
+            ScreenSize::new(super::LCD_COLUMNS as i32, super::LCD_ROWS as i32),
+        );
+        self.draw_area(location, screen)
+    }
+
+    /// Makes `sprite` display this map, sized to fit it with its top left corner at
+    /// `location`.  The sprite switches to custom drawing, so its `Game::draw_sprite` call must
+    /// be passed on to `draw_sprite`.  From then on, changed tiles are marked as dirty rects
+    /// so only they are redrawn.
+    pub fn attach_to_sprite(
+        &mut self,
+        mut sprite: Sprite,
+        location: ScreenPoint,
+    ) -> Result<(), Error> {
+        let size = self.get_pixel_size();
+        sprite.set_bounds(&PDRect {
+            x: location.x as f32,
+            y: location.y as f32,
+            width: size.width as f32,
+            height: size.height as f32,
+        })?;
+        sprite.set_use_custom_draw()?;
+        self.sprite = Some(sprite);
+        Ok(())
+    }
+
+    pub fn get_sprite(&self) -> Option<&Sprite> {
+        self.sprite.as_ref()
+    }
+
+    /// Stops marking dirty rects for the attached sprite and gives it back.
+    pub fn detach_sprite(&mut self) -> Option<Sprite> {
+        self.sprite.take()
+    }
+
+    /// Draws the tiles under `draw_rect` for an attached sprite's `Game::draw_sprite`.
+    pub fn draw_sprite(&self, bounds: &PDRect, draw_rect: &PDRect) -> Result<(), Error> {
+        let location = ScreenPoint::new(bounds.x as i32, bounds.y as i32);
+        let area = ScreenRect::new(
+            ScreenPoint::new(draw_rect.x as i32, draw_rect.y as i32),
+            ScreenSize::new(
+                libm::ceilf(draw_rect.width) as i32,
+                libm::ceilf(draw_rect.height) as i32,
+            ),
+        );
+        self.draw_area(location, area)
+    }
+
+    fn draw_area(&self, location: ScreenPoint, area: ScreenRect) -> Result<(), Error> {
+        let map = ScreenRect::new(location, self.get_pixel_size());
+        let visible = match map.intersection(&area) {
+            Some(visible) => visible,
+            None => return Ok(()),
+        };
+        let first_x = ((visible.min_x() - location.x) / self.tile_size.width) as usize;
+        let first_y = ((visible.min_y() - location.y) / self.tile_size.height) as usize;
+        let last_x = ((visible.max_x() - 1 - location.x) / self.tile_size.width) as usize;
+        let last_y = ((visible.max_y() - 1 - location.y) / self.tile_size.height) as usize;
+
+        for y in first_y..=last_y.min(self.height - 1) {
+            for x in first_x..=last_x.min(self.width - 1) {
+                if let Some(tile) = self.tiles[y * self.width + x] {
+                    let bitmap = self.get_tile_bitmap(tile.index)?;
+                    let tile_location = ScreenPoint::new(
+                        location.x + x as i32 * self.tile_size.width,
+                        location.y + y as i32 * self.tile_size.height,
+                    );
+                    bitmap.draw(tile_location, tile.flip)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn get_tile_bitmap(&self, index: usize) -> Result<Bitmap, Error> {
+        let frame = match self.animations.get(&index) {
+            Some(clip) => clip.frame_at(self.elapsed_ms),
+            None => index,
+        };
+        self.table.get_bitmap(frame)
+    }
+
+    /// Marks the whole map for redraw, if it's attached to a sprite.
+    pub fn mark_dirty(&mut self) -> Result<(), Error> {
+        if let Some(sprite) = self.sprite.as_mut() {
+            sprite.mark_dirty()?;
+        }
+        Ok(())
+    }
+
+    fn mark_index_dirty(&mut self, index: usize) -> Result<(), Error> {
+        if self.sprite.is_none() {
+            return Ok(());
+        }
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if matches!(self.tiles[y * self.width + x], Some(tile) if tile.index == index) {
+                    self.mark_tile_dirty(x, y)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn mark_tile_dirty(&self, x: usize, y: usize) -> Result<(), Error> {
+        let sprite = match &self.sprite {
+            Some(sprite) => sprite,
+            None => return Ok(()),
+        };
+        // Dirty rects are in screen coordinates, so apply the draw offset.
+        let bounds = sprite.get_bounds()?;
+        let offset = Graphics::get().get_draw_offset();
+        let left = bounds.x as i32 + x as i32 * self.tile_size.width + offset.x;
+        let top = bounds.y as i32 + y as i32 * self.tile_size.height + offset.y;
+        SpriteManager::add_dirty_rect(LCDRect {
+            left,
+            right: left + self.tile_size.width,
+            top,
+            bottom: top + self.tile_size.height,
+        })
+    }
+}
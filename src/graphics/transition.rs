@@ -0,0 +1,311 @@
+//! Animated transitions from one screen to the next.
+//!
+//! A `Transition` captures the last frame drawn and, each frame after, draws what's left of
+//! it over the new screen.  Draw the incoming screen as usual, then call `update` last:
+//!
+//! ```ignore
+//! // When switching scenes:
+//! self.transition = Some(
+//!     Transition::new(TransitionEffect::Iris(Iris::Close), 600)?
+//!         .on_complete(|| System::log_to_console("transition done")),
+//! );
+//!
+//! // In update, after drawing the new scene:
+//! if let Some(transition) = &mut self.transition {
+//!     if transition.update(delta_ms)? {
+//!         self.transition = None;
+//!     }
+//! }
+//! ```
+
+use {
+    super::{pattern, Bitmap, Graphics, LCDBitmapFlip, LCDColor, LCDSolidColor},
+    crate::{
+        display::Display,
+        geometry::{GrPoint, ScreenPoint, ScreenRect, ScreenSize, ScreenVector},
+        log_to_console,
+    },
+    alloc::boxed::Box,
+    anyhow::{anyhow, Error},
+    core::fmt,
+    euclid::default::Vector2D,
+};
+
+/// The direction the edge of the old screen moves in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Iris {
+    /// A hole opens in the middle of the old screen and grows to show the new one.
+    Open,
+    /// The old screen shrinks into a circle in the middle and disappears.
+    Close,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransitionEffect {
+    /// The new screen is uncovered by an edge sweeping across in the given direction.
+    Wipe(Direction),
+    /// The old screen dissolves into the new one through a dither pattern.
+    Fade,
+    Iris(Iris),
+    /// The old screen slides off in the given direction.
+    Slide(Direction),
+    /// The old screen breaks up into large pixels, then the new one comes into focus.
+    Mosaic,
+}
+
+// The largest mosaic the display supports.
+const MAX_MOSAIC: f32 = 3.0;
+
+pub struct Transition {
+    outgoing: Bitmap,
+    // The outgoing frame's mask, drawn into each frame to hide the parts that are gone.
+    mask: Option<Bitmap>,
+    size: ScreenSize,
+    effect: TransitionEffect,
+    duration_ms: usize,
+    elapsed_ms: usize,
+    easing: fn(f32) -> f32,
+    on_complete: Option<Box<dyn FnOnce()>>,
+    finished: bool,
+}
+
+impl Transition {
+    /// Starts a transition away from the frame most recently drawn.
+    pub fn new(effect: TransitionEffect, duration_ms: usize) -> Result<Self, Error> {
+        let outgoing = Graphics::get().copy_framebuffer()?;
+        Self::from_bitmap(outgoing, effect, duration_ms)
+    }
+
+    /// Starts a transition away from `outgoing`, which should be the size of the screen.
+    pub fn from_bitmap(
+        outgoing: Bitmap,
+        effect: TransitionEffect,
+        duration_ms: usize,
+    ) -> Result<Self, Error> {
+        let data = outgoing.get_data()?;
+        let size = ScreenSize::new(data.width, data.height);
+        let (outgoing, mask) = match effect {
+            TransitionEffect::Wipe(_) | TransitionEffect::Fade | TransitionEffect::Iris(_) => {
+                // Give a copy a mask to draw into, leaving the caller's bitmap alone.
+                let copy = outgoing.duplicate()?;
+                copy.set_bitmap_mask(
+                    Graphics::get()
+                        .new_bitmap(size, LCDColor::Solid(LCDSolidColor::kColorWhite))?,
+                )?;
+                let mask = copy
+                    .mask()?
+                    .ok_or_else(|| anyhow!("transition bitmap has no mask"))?;
+                (copy, Some(mask))
+            }
+            _ => (outgoing, None),
+        };
+        Ok(Self {
+            outgoing,
+            mask,
+            size,
+            effect,
+            duration_ms,
+            elapsed_ms: 0,
+            easing: linear,
+            on_complete: None,
+            finished: false,
+        })
+    }
+
+    /// Calls `f` once, from `update`, when the transition finishes.
+    pub fn on_complete<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce() + 'static,
+    {
+        self.on_complete = Some(Box::new(f));
+        self
+    }
+
+    /// Shapes the progress of the transition; `easing` maps time from 0.0 to 1.0 onto
+    /// progress from 0.0 to 1.0.  The default is linear.
+    pub fn with_easing(mut self, easing: fn(f32) -> f32) -> Self {
+        self.easing = easing;
+        self
+    }
+
+    pub fn get_effect(&self) -> TransitionEffect {
+        self.effect
+    }
+
+    /// Returns how far through the transition is, from 0.0 to 1.0, after easing.
+    pub fn progress(&self) -> f32 {
+        let t = if self.duration_ms == 0 {
+            1.0
+        } else {
+            (self.elapsed_ms as f32 / self.duration_ms as f32).min(1.0)
+        };
+        (self.easing)(t)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Advances the transition by `delta_ms` and draws what's left of the old screen.  Call
+    /// after drawing the new screen each frame.  Returns true once the transition has
+    /// finished, after calling the completion callback.
+    pub fn update(&mut self, delta_ms: usize) -> Result<bool, Error> {
+        if self.finished {
+            return Ok(true);
+        }
+        self.elapsed_ms = self.elapsed_ms.saturating_add(delta_ms);
+        let done = self.elapsed_ms >= self.duration_ms;
+        if done {
+            self.finish()?;
+        } else {
+            // The old frame is in screen coordinates, so draw it without the draw offset.
+            let graphics = Graphics::get();
+            let offset = graphics.get_draw_offset();
+            graphics.set_draw_offset(ScreenVector::zero())?;
+            let drawn = self.draw(self.progress());
+            graphics.set_draw_offset(offset)?;
+            drawn?;
+        }
+        Ok(done)
+    }
+
+    /// Ends the transition now, calling the completion callback.
+    pub fn finish(&mut self) -> Result<(), Error> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+        self.elapsed_ms = self.duration_ms;
+        if self.effect == TransitionEffect::Mosaic {
+            Display::get().set_mosaic(Vector2D::new(0, 0))?;
+        }
+        if let Some(on_complete) = self.on_complete.take() {
+            on_complete();
+        }
+        Ok(())
+    }
+
+    fn draw(&self, progress: f32) -> Result<(), Error> {
+        let graphics = Graphics::get();
+        let (width, height) = (self.size.width, self.size.height);
+        let origin = ScreenPoint::zero();
+
+        if let Some(mask) = &self.mask {
+            graphics.with_context(mask, || {
+                let hidden = LCDColor::Solid(LCDSolidColor::kColorBlack);
+                let shown = LCDColor::Solid(LCDSolidColor::kColorWhite);
+                match self.effect {
+                    TransitionEffect::Wipe(direction) => {
+                        graphics.clear(shown)?;
+                        graphics.fill_rect(wiped(direction, self.size, progress), hidden)
+                    }
+                    TransitionEffect::Fade => {
+                        let level = pattern::GREY_LEVELS - 1;
+                        graphics.clear(LCDColor::Pattern(pattern::grey_between(level, 0, progress)))
+                    }
+                    TransitionEffect::Iris(iris) => {
+                        let center = GrPoint::new(width as f32 / 2.0, height as f32 / 2.0);
+                        // Far enough to uncover the corners.
+                        let max_radius = libm::sqrtf(center.x * center.x + center.y * center.y);
+                        match iris {
+                            Iris::Open => {
+                                graphics.clear(shown)?;
+                                graphics.fill_circle_at_center(
+                                    center,
+                                    max_radius * progress,
+                                    hidden,
+                                )
+                            }
+                            Iris::Close => {
+                                graphics.clear(hidden)?;
+                                graphics.fill_circle_at_center(
+                                    center,
+                                    max_radius * (1.0 - progress),
+                                    shown,
+                                )
+                            }
+                        }
+                    }
+                    _ => Ok(()),
+                }
+            })?;
+            return self.outgoing.draw(origin, LCDBitmapFlip::kBitmapUnflipped);
+        }
+
+        match self.effect {
+            TransitionEffect::Slide(direction) => {
+                let distance = |size: i32| (size as f32 * progress) as i32;
+                let location = match direction {
+                    Direction::Left => ScreenPoint::new(-distance(width), 0),
+                    Direction::Right => ScreenPoint::new(distance(width), 0),
+                    Direction::Up => ScreenPoint::new(0, -distance(height)),
+                    Direction::Down => ScreenPoint::new(0, distance(height)),
+                };
+                self.outgoing
+                    .draw(location, LCDBitmapFlip::kBitmapUnflipped)
+            }
+            TransitionEffect::Mosaic => {
+                // Break up the old screen over the first half, then bring the new one into
+                // focus over the second.
+                let amount = if progress < 0.5 {
+                    self.outgoing
+                        .draw(origin, LCDBitmapFlip::kBitmapUnflipped)?;
+                    progress * 2.0
+                } else {
+                    (1.0 - progress) * 2.0
+                };
+                let amount = libm::roundf(amount * MAX_MOSAIC) as u32;
+                Display::get().set_mosaic(Vector2D::new(amount, amount))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Drop for Transition {
+    fn drop(&mut self) {
+        // Don't leave the whole display mosaicked if the transition is abandoned part way.
+        if !self.finished && self.effect == TransitionEffect::Mosaic {
+            if let Err(err) = Display::get().set_mosaic(Vector2D::new(0, 0)) {
+                log_to_console!("Error resetting mosaic for dropped transition: {err:#}");
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Transition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Transition")
+            .field("effect", &self.effect)
+            .field("duration_ms", &self.duration_ms)
+            .field("elapsed_ms", &self.elapsed_ms)
+            .field("finished", &self.finished)
+            .finish()
+    }
+}
+
+fn linear(t: f32) -> f32 {
+    t
+}
+
+/// Returns the part of the screen the wipe has passed over.
+fn wiped(direction: Direction, size: ScreenSize, progress: f32) -> ScreenRect {
+    let (width, height) = (size.width, size.height);
+    let across = (width as f32 * progress) as i32;
+    let down = (height as f32 * progress) as i32;
+    let (origin, size) = match direction {
+        Direction::Right => ((0, 0), (across, height)),
+        Direction::Left => ((width - across, 0), (across, height)),
+        Direction::Down => ((0, 0), (width, down)),
+        Direction::Up => ((0, height - down), (width, down)),
+    };
+    ScreenRect::new(origin.into(), size.into())
+}
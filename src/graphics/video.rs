@@ -0,0 +1,181 @@
+//! Video playback of `.pdv` files.
+//!
+//! The system decodes one frame at a time on request; there's no playback clock.  Pick the
+//! frame to show from elapsed time, or from a `FilePlayer` playing the soundtrack so picture
+//! and sound stay in sync:
+//!
+//! ```ignore
+//! let mut video = VideoPlayer::load("cutscene.pdv")?;
+//! let music = Sound::get().get_file_player()?;
+//! music.load_into_player("cutscene.pda")?;
+//! music.play(1)?;
+//!
+//! // In update:
+//! video.render_synced(&music)?;
+//! ```
+
+use {
+    super::{Bitmap, Graphics},
+    crate::{geometry::ScreenSize, pd_func_caller, pd_func_caller_log, sound::FilePlayer},
+    alloc::string::String,
+    anyhow::{anyhow, ensure, Error},
+    crankstart_sys::{ctypes::c_int, playdate_video, LCDVideoPlayer},
+    cstr_core::{CStr, CString},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VideoInfo {
+    pub size: ScreenSize,
+    /// Frames per second.
+    pub frame_rate: f32,
+    pub frame_count: usize,
+    /// The frame most recently rendered.
+    pub current_frame: usize,
+}
+
+impl VideoInfo {
+    /// Returns the length of the video in seconds.
+    pub fn duration(&self) -> f32 {
+        if self.frame_rate > 0.0 {
+            self.frame_count as f32 / self.frame_rate
+        } else {
+            0.0
+        }
+    }
+
+    /// Returns the frame to show `seconds` into the video, stopping at the last frame.
+    pub fn frame_at(&self, seconds: f32) -> usize {
+        let frame = (seconds.max(0.0) * self.frame_rate) as usize;
+        frame.min(self.frame_count.saturating_sub(1))
+    }
+}
+
+/// Renders frames of a video to the screen or into a bitmap.  The player is freed when this
+/// is dropped.
+#[derive(Debug)]
+pub struct VideoPlayer {
+    raw_player: *mut LCDVideoPlayer,
+    // The bitmap being rendered into, kept alive while the player draws into it.
+    context: Option<Bitmap>,
+    // The last frame rendered by `render_synced`, so unchanged frames aren't decoded again.
+    synced_frame: Option<usize>,
+}
+
+impl VideoPlayer {
+    /// Opens the video at `path`, which renders to the screen until `set_context` is called.
+    pub fn load(path: &str) -> Result<Self, Error> {
+        let c_path = CString::new(path).map_err(Error::msg)?;
+        let raw_player = pd_func_caller!((*video()).loadVideo, c_path.as_ptr())?;
+        ensure!(!raw_player.is_null(), "Couldn't load video {}", path);
+        pd_func_caller!((*video()).useScreenContext, raw_player)?;
+        Ok(Self {
+            raw_player,
+            context: None,
+            synced_frame: None,
+        })
+    }
+
+    pub fn info(&self) -> Result<VideoInfo, Error> {
+        let mut width = 0;
+        let mut height = 0;
+        let mut frame_rate = 0.0;
+        let mut frame_count = 0;
+        let mut current_frame = 0;
+        pd_func_caller!(
+            (*video()).getInfo,
+            self.raw_player,
+            &mut width,
+            &mut height,
+            &mut frame_rate,
+            &mut frame_count,
+            &mut current_frame,
+        )?;
+        Ok(VideoInfo {
+            size: ScreenSize::new(width, height),
+            frame_rate,
+            frame_count: frame_count.max(0) as usize,
+            current_frame: current_frame.max(0) as usize,
+        })
+    }
+
+    /// Renders frames into `bitmap` from now on.  It should be the size of the video.
+    pub fn set_context(&mut self, bitmap: &Bitmap) -> Result<(), Error> {
+        let raw_bitmap = bitmap.inner.borrow().raw_bitmap;
+        let result = pd_func_caller!((*video()).setContext, self.raw_player, raw_bitmap)?;
+        if result == 0 {
+            return Err(self.error("setContext"));
+        }
+        self.context = Some(bitmap.clone());
+        self.synced_frame = None;
+        Ok(())
+    }
+
+    /// Renders frames straight into the frame buffer from now on.
+    pub fn use_screen_context(&mut self) -> Result<(), Error> {
+        pd_func_caller!((*video()).useScreenContext, self.raw_player)?;
+        self.context = None;
+        self.synced_frame = None;
+        Ok(())
+    }
+
+    /// Returns the bitmap set with `set_context`, if frames are rendered into one.
+    pub fn get_context(&self) -> Option<&Bitmap> {
+        self.context.as_ref()
+    }
+
+    /// Decodes frame `frame` into the screen or the context bitmap.
+    pub fn render_frame(&self, frame: usize) -> Result<(), Error> {
+        let result = pd_func_caller!((*video()).renderFrame, self.raw_player, frame as c_int)?;
+        if result == 0 {
+            return Err(self.error("renderFrame"));
+        }
+        Ok(())
+    }
+
+    /// Switches to rendering into `bitmap` and renders frame `frame` there.
+    pub fn render_frame_into(&mut self, bitmap: &Bitmap, frame: usize) -> Result<(), Error> {
+        self.set_context(bitmap)?;
+        self.render_frame(frame)
+    }
+
+    /// Renders the frame matching how far `player` is through its file, for keeping a video
+    /// in step with its soundtrack.  Returns true if a new frame was rendered, false if the
+    /// frame hasn't changed since the last call.
+    pub fn render_synced(&mut self, player: &FilePlayer) -> Result<bool, Error> {
+        let frame = self.info()?.frame_at(player.get_offset()?);
+        if self.synced_frame == Some(frame) {
+            return Ok(false);
+        }
+        self.render_frame(frame)?;
+        self.synced_frame = Some(frame);
+        Ok(true)
+    }
+
+    /// Returns the description of the last error, if any.
+    pub fn get_error(&self) -> Result<Option<String>, Error> {
+        let err = pd_func_caller!((*video()).getError, self.raw_player)?;
+        if err.is_null() {
+            return Ok(None);
+        }
+        Ok(Some(unsafe {
+            CStr::from_ptr(err).to_string_lossy().into_owned()
+        }))
+    }
+
+    fn error(&self, function: &str) -> Error {
+        match self.get_error() {
+            Ok(Some(message)) => anyhow!("video.{} failed: {}", function, message),
+            _ => anyhow!("video.{} failed", function),
+        }
+    }
+}
+
+impl Drop for VideoPlayer {
+    fn drop(&mut self) {
+        pd_func_caller_log!((*video()).freePlayer, self.raw_player);
+    }
+}
+
+fn video() -> *const playdate_video {
+    unsafe { (*Graphics::get_ptr()).video }
+}
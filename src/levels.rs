@@ -0,0 +1,244 @@
+//! Loading of levels made in the [Tiled](https://www.mapeditor.org) and
+//! [LDtk](https://ldtk.io) editors.  Requires the `levels` feature.
+//!
+//! Both formats load into the same `Level` type: tile layers ready to become `Tilemap`s,
+//! object layers of `Entity`s, and the rectangles that should block movement.
+//!
+//! ```ignore
+//! let level = Level::load_tiled("levels/forest.tmj")?;
+//! let tiles = graphics.load_bitmap_table("images/forest")?;
+//! let ground = level.tile_layer("ground").unwrap().to_tilemap(tiles)?;
+//! let walls = level.add_collision_sprites(CollisionTags::default())?;
+//! let enemies: Vec<Enemy> = level.entities()?;
+//! ```
+
+use {
+    crate::{
+        file::FileSystem,
+        geometry::{GrRect, ScreenSize, ScreenVector},
+        graphics::{BitmapTable, Tile, Tilemap},
+        sprite::collision::{merge_cells, CollisionKind, CollisionTags, StaticCollision},
+    },
+    alloc::{string::String, vec::Vec},
+    anyhow::{ensure, Error},
+    hashbrown::HashMap,
+};
+
+pub mod ldtk;
+pub use ldtk::LdtkProject;
+pub mod tiled;
+
+/// A custom property of an entity, as set in the editor.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PropertyValue {
+    Bool(bool),
+    Int(i64),
+    Float(f32),
+    /// Strings, along with colors, file paths and enum values, which both editors store as
+    /// strings.
+    String(String),
+}
+
+/// A layer of tiles, listed with the tile under the top left corner first.
+#[derive(Clone, Debug)]
+pub struct TileLayer {
+    pub name: String,
+    /// Size in tiles.
+    pub width: usize,
+    pub height: usize,
+    pub tile_size: ScreenSize,
+    /// Where the layer is drawn relative to the level's top left corner.
+    pub offset: ScreenVector,
+    pub visible: bool,
+    /// Path of the tileset image, relative to the level file, as given by the editor.  Load
+    /// the matching `BitmapTable` to draw the layer.
+    pub tileset: Option<String>,
+    /// Row by row; tile indexes count from zero in the tileset.
+    pub tiles: Vec<Option<Tile>>,
+}
+
+impl TileLayer {
+    /// Makes a `Tilemap` of this layer drawn with `table`, which should hold the tileset's
+    /// tiles in the same order.
+    pub fn to_tilemap(&self, table: BitmapTable) -> Result<Tilemap, Error> {
+        let mut tilemap = Tilemap::new(table, self.width, self.height)?;
+        ensure!(
+            tilemap.get_tile_size() == self.tile_size,
+            "layer {} has {:?} tiles but the table's are {:?}",
+            self.name,
+            self.tile_size,
+            tilemap.get_tile_size()
+        );
+        tilemap.set_tiles(&self.tiles)?;
+        Ok(tilemap)
+    }
+}
+
+/// A grid of integer values, from an LDtk IntGrid layer.  Zero means empty.
+#[derive(Clone, Debug)]
+pub struct IntGridLayer {
+    pub name: String,
+    pub width: usize,
+    pub height: usize,
+    pub cell_size: i32,
+    pub offset: ScreenVector,
+    /// Row by row, starting at the top left.
+    pub values: Vec<i32>,
+}
+
+impl IntGridLayer {
+    pub fn get(&self, x: usize, y: usize) -> i32 {
+        if x < self.width && y < self.height {
+            self.values[y * self.width + x]
+        } else {
+            0
+        }
+    }
+}
+
+/// An object placed in the editor: a spawn point, enemy, trigger area and so on.
+#[derive(Clone, Debug)]
+pub struct Entity {
+    /// Tiled's object id or LDtk's instance id.
+    pub id: String,
+    pub name: String,
+    /// Tiled's object class or LDtk's entity identifier, for telling kinds of entity apart.
+    pub kind: String,
+    /// Position and size in pixels from the level's top left corner.  Point objects have zero
+    /// size.
+    pub rect: GrRect,
+    pub properties: HashMap<String, PropertyValue>,
+}
+
+impl Entity {
+    pub fn get_bool(&self, name: &str) -> Option<bool> {
+        match self.properties.get(name)? {
+            PropertyValue::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn get_int(&self, name: &str) -> Option<i64> {
+        match self.properties.get(name)? {
+            PropertyValue::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Returns a number property; integers are converted.
+    pub fn get_float(&self, name: &str) -> Option<f32> {
+        match self.properties.get(name)? {
+            PropertyValue::Float(value) => Some(*value),
+            PropertyValue::Int(value) => Some(*value as f32),
+            _ => None,
+        }
+    }
+
+    pub fn get_str(&self, name: &str) -> Option<&str> {
+        match self.properties.get(name)? {
+            PropertyValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ObjectLayer {
+    pub name: String,
+    pub offset: ScreenVector,
+    pub visible: bool,
+    pub entities: Vec<Entity>,
+}
+
+/// Game types built from entities, for `Level::entities`.
+pub trait FromEntity: Sized {
+    /// Builds a value from `entity`, or returns None for kinds of entity this type doesn't
+    /// represent.
+    fn from_entity(entity: &Entity) -> Result<Option<Self>, Error>;
+}
+
+#[derive(Clone, Debug)]
+pub struct Level {
+    pub name: String,
+    /// Size in pixels.
+    pub size: ScreenSize,
+    /// Position in the world, for LDtk projects laid out as a world map.
+    pub world_offset: ScreenVector,
+    /// All layers are listed bottom first, in the order they should be drawn.
+    pub tile_layers: Vec<TileLayer>,
+    pub int_grid_layers: Vec<IntGridLayer>,
+    pub object_layers: Vec<ObjectLayer>,
+    /// Rectangles that should block movement, in pixels from the level's top left corner.
+    /// These come from Tiled objects on a layer named "collision", or of class "collision",
+    /// and from the solid cells of LDtk IntGrid layers named "collision", in any case.
+    pub collision: Vec<GrRect>,
+}
+
+impl Level {
+    /// Loads a Tiled map saved as JSON (`.tmj`).  Tile layer data must be uncompressed.
+    pub fn load_tiled(path: &str) -> Result<Level, Error> {
+        let data = FileSystem::get().read_file(path)?;
+        tiled::parse(&data)
+    }
+
+    pub fn tile_layer(&self, name: &str) -> Option<&TileLayer> {
+        self.tile_layers.iter().find(|layer| layer.name == name)
+    }
+
+    pub fn int_grid_layer(&self, name: &str) -> Option<&IntGridLayer> {
+        self.int_grid_layers.iter().find(|layer| layer.name == name)
+    }
+
+    pub fn object_layer(&self, name: &str) -> Option<&ObjectLayer> {
+        self.object_layers.iter().find(|layer| layer.name == name)
+    }
+
+    /// Iterates over the entities of every object layer.
+    pub fn all_entities(&self) -> impl Iterator<Item = &Entity> {
+        self.object_layers
+            .iter()
+            .flat_map(|layer| layer.entities.iter())
+    }
+
+    /// Builds a `T` from every entity that it represents.
+    pub fn entities<T: FromEntity>(&self) -> Result<Vec<T>, Error> {
+        let mut out = Vec::new();
+        for entity in self.all_entities() {
+            if let Some(value) = T::from_entity(entity)? {
+                out.push(value);
+            }
+        }
+        Ok(out)
+    }
+
+    /// Adds an invisible, solid sprite to the `SpriteManager` for each collision rectangle,
+    /// offset by `world_offset` and tagged with `tags.solid`.  The sprites are removed and
+    /// freed when the returned `StaticCollision` is dropped, so keep it for as long as the
+    /// level is loaded.
+    pub fn add_collision_sprites(&self, tags: CollisionTags) -> Result<StaticCollision, Error> {
+        let offset = self.world_offset.to_f32();
+        let rects = self
+            .collision
+            .iter()
+            .map(|rect| rect.translate(offset).round().to_i32());
+        StaticCollision::from_rects(rects, CollisionKind::Solid, tags)
+    }
+}
+
+/// Merges the solid cells of `grid` into rectangles.
+fn solid_rects(grid: &IntGridLayer) -> Vec<GrRect> {
+    let size = grid.cell_size as f32;
+    merge_cells(grid.width, grid.height, |x, y| grid.get(x, y) != 0)
+        .into_iter()
+        .map(|rect| {
+            GrRect::new(
+                (
+                    grid.offset.x as f32 + rect.x as f32 * size,
+                    grid.offset.y as f32 + rect.y as f32 * size,
+                )
+                    .into(),
+                (rect.width as f32 * size, rect.height as f32 * size).into(),
+            )
+        })
+        .collect()
+}
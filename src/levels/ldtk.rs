@@ -0,0 +1,275 @@
+//! LDtk projects (`.ldtk`).
+
+use {
+    super::{solid_rects, Entity, IntGridLayer, Level, ObjectLayer, PropertyValue, TileLayer},
+    crate::{
+        file::FileSystem,
+        geometry::{GrRect, ScreenSize, ScreenVector},
+        graphics::{LCDBitmapFlip, Tile},
+    },
+    alloc::{string::String, vec, vec::Vec},
+    anyhow::{anyhow, ensure, Error},
+    hashbrown::HashMap,
+    serde::Deserialize,
+    serde_json::Value,
+};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Project {
+    #[serde(default)]
+    external_levels: bool,
+    levels: Vec<RawLevel>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawLevel {
+    identifier: String,
+    #[serde(default)]
+    world_x: i32,
+    #[serde(default)]
+    world_y: i32,
+    px_wid: i32,
+    px_hei: i32,
+    /// Missing when levels are saved in separate files.
+    #[serde(default)]
+    layer_instances: Option<Vec<LayerInstance>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LayerInstance {
+    #[serde(rename = "__identifier")]
+    identifier: String,
+    #[serde(rename = "__type")]
+    kind: String,
+    #[serde(rename = "__cWid")]
+    c_wid: usize,
+    #[serde(rename = "__cHei")]
+    c_hei: usize,
+    #[serde(rename = "__gridSize")]
+    grid_size: i32,
+    #[serde(rename = "__pxTotalOffsetX", default)]
+    px_total_offset_x: i32,
+    #[serde(rename = "__pxTotalOffsetY", default)]
+    px_total_offset_y: i32,
+    #[serde(rename = "__tilesetRelPath", default)]
+    tileset_rel_path: Option<String>,
+    #[serde(default = "visible")]
+    visible: bool,
+    #[serde(default)]
+    int_grid_csv: Vec<i32>,
+    #[serde(default)]
+    grid_tiles: Vec<TileInstance>,
+    #[serde(default)]
+    auto_layer_tiles: Vec<TileInstance>,
+    #[serde(default)]
+    entity_instances: Vec<EntityInstance>,
+}
+
+fn visible() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+struct TileInstance {
+    /// Position in the layer, in pixels.
+    px: [i32; 2],
+    /// Flip bits: 1 for X, 2 for Y.
+    #[serde(default)]
+    f: u8,
+    t: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EntityInstance {
+    #[serde(rename = "__identifier")]
+    identifier: String,
+    iid: String,
+    /// Position of the pivot, in pixels from the layer's top left corner.
+    px: [i32; 2],
+    #[serde(rename = "__pivot", default)]
+    pivot: [f32; 2],
+    width: i32,
+    height: i32,
+    #[serde(default)]
+    field_instances: Vec<FieldInstance>,
+}
+
+#[derive(Deserialize)]
+struct FieldInstance {
+    #[serde(rename = "__identifier")]
+    identifier: String,
+    #[serde(rename = "__value")]
+    value: Value,
+}
+
+/// All the levels of an LDtk project.
+#[derive(Clone, Debug)]
+pub struct LdtkProject {
+    pub levels: Vec<Level>,
+}
+
+impl LdtkProject {
+    /// Loads a project saved with its levels in the main file, which is LDtk's default.
+    pub fn load(path: &str) -> Result<Self, Error> {
+        let data = FileSystem::get().read_file(path)?;
+        Self::parse(&data)
+    }
+
+    /// Parses the JSON text of a `.ldtk` file.
+    pub fn parse(json: &[u8]) -> Result<Self, Error> {
+        let project: Project = serde_json::from_slice(json).map_err(|err| anyhow!("{}", err))?;
+        ensure!(
+            !project.external_levels,
+            "LDtk levels saved in separate files aren't supported"
+        );
+        let levels = project
+            .levels
+            .into_iter()
+            .map(level)
+            .collect::<Result<_, _>>()?;
+        Ok(Self { levels })
+    }
+
+    pub fn level(&self, name: &str) -> Option<&Level> {
+        self.levels.iter().find(|level| level.name == name)
+    }
+}
+
+fn level(raw: RawLevel) -> Result<Level, Error> {
+    let mut level = Level {
+        name: raw.identifier,
+        size: ScreenSize::new(raw.px_wid, raw.px_hei),
+        world_offset: ScreenVector::new(raw.world_x, raw.world_y),
+        tile_layers: Vec::new(),
+        int_grid_layers: Vec::new(),
+        object_layers: Vec::new(),
+        collision: Vec::new(),
+    };
+
+    // LDtk lists the top layer first.
+    for layer in raw.layer_instances.unwrap_or_default().into_iter().rev() {
+        let offset = ScreenVector::new(layer.px_total_offset_x, layer.px_total_offset_y);
+        match layer.kind.as_str() {
+            "IntGrid" | "AutoLayer" | "Tiles" => {
+                if !layer.int_grid_csv.is_empty() {
+                    ensure!(
+                        layer.int_grid_csv.len() == layer.c_wid * layer.c_hei,
+                        "layer {} has {} cells, expected {}",
+                        layer.identifier,
+                        layer.int_grid_csv.len(),
+                        layer.c_wid * layer.c_hei
+                    );
+                    let grid = IntGridLayer {
+                        name: layer.identifier.clone(),
+                        width: layer.c_wid,
+                        height: layer.c_hei,
+                        cell_size: layer.grid_size,
+                        offset,
+                        values: layer.int_grid_csv.clone(),
+                    };
+                    if grid.name.eq_ignore_ascii_case("collision") {
+                        level.collision.extend(solid_rects(&grid));
+                    }
+                    level.int_grid_layers.push(grid);
+                }
+                // IntGrid layers can have auto-layer tiles too.
+                let instances = if layer.grid_tiles.is_empty() {
+                    &layer.auto_layer_tiles
+                } else {
+                    &layer.grid_tiles
+                };
+                if !instances.is_empty() {
+                    level
+                        .tile_layers
+                        .push(tile_layer(&layer, instances, offset));
+                }
+            }
+            "Entities" => {
+                let entities = layer
+                    .entity_instances
+                    .iter()
+                    .map(|instance| entity(instance, offset))
+                    .collect();
+                level.object_layers.push(ObjectLayer {
+                    name: layer.identifier,
+                    offset,
+                    visible: layer.visible,
+                    entities,
+                });
+            }
+            _ => {}
+        }
+    }
+    Ok(level)
+}
+
+fn tile_layer(
+    layer: &LayerInstance,
+    instances: &[TileInstance],
+    offset: ScreenVector,
+) -> TileLayer {
+    let mut tiles = vec![None; layer.c_wid * layer.c_hei];
+    let size = layer.grid_size.max(1);
+    // Auto-layers can stack several tiles in one cell; the last one listed is on top, and
+    // it's the only one a tilemap can show.
+    for instance in instances {
+        let x = (instance.px[0] / size) as usize;
+        let y = (instance.px[1] / size) as usize;
+        if x >= layer.c_wid || y >= layer.c_hei {
+            continue;
+        }
+        let flip = match instance.f & 3 {
+            0 => LCDBitmapFlip::kBitmapUnflipped,
+            1 => LCDBitmapFlip::kBitmapFlippedX,
+            2 => LCDBitmapFlip::kBitmapFlippedY,
+            _ => LCDBitmapFlip::kBitmapFlippedXY,
+        };
+        tiles[y * layer.c_wid + x] = Some(Tile::flipped(instance.t, flip));
+    }
+    TileLayer {
+        name: layer.identifier.clone(),
+        width: layer.c_wid,
+        height: layer.c_hei,
+        tile_size: ScreenSize::new(layer.grid_size, layer.grid_size),
+        offset,
+        visible: layer.visible,
+        tileset: layer.tileset_rel_path.clone(),
+        tiles,
+    }
+}
+
+fn entity(instance: &EntityInstance, offset: ScreenVector) -> Entity {
+    let x = instance.px[0] as f32 - instance.pivot[0] * instance.width as f32;
+    let y = instance.px[1] as f32 - instance.pivot[1] * instance.height as f32;
+    let properties = instance
+        .field_instances
+        .iter()
+        .filter_map(|field| {
+            let value = match &field.value {
+                Value::Bool(value) => PropertyValue::Bool(*value),
+                Value::Number(value) if value.is_f64() => {
+                    PropertyValue::Float(value.as_f64().unwrap_or_default() as f32)
+                }
+                Value::Number(value) => PropertyValue::Int(value.as_i64().unwrap_or_default()),
+                Value::String(value) => PropertyValue::String(value.clone()),
+                // Null for unset fields; arrays and points aren't supported.
+                _ => return None,
+            };
+            Some((field.identifier.clone(), value))
+        })
+        .collect::<HashMap<_, _>>();
+    Entity {
+        id: instance.iid.clone(),
+        name: instance.identifier.clone(),
+        kind: instance.identifier.clone(),
+        rect: GrRect::new(
+            (x + offset.x as f32, y + offset.y as f32).into(),
+            (instance.width as f32, instance.height as f32).into(),
+        ),
+        properties,
+    }
+}
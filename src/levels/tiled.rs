@@ -0,0 +1,307 @@
+//! Tiled maps saved in its JSON format (`.tmj`).
+
+use {
+    super::{Entity, Level, ObjectLayer, PropertyValue, TileLayer},
+    crate::{
+        geometry::{GrRect, ScreenSize, ScreenVector},
+        graphics::{LCDBitmapFlip, Tile},
+    },
+    alloc::{format, string::String, vec::Vec},
+    anyhow::{anyhow, bail, ensure, Error},
+    hashbrown::HashMap,
+    serde::Deserialize,
+    serde_json::Value,
+};
+
+// Tiled stores flips in the top bits of each tile id.
+const FLIPPED_HORIZONTALLY: u32 = 0x8000_0000;
+const FLIPPED_VERTICALLY: u32 = 0x4000_0000;
+const FLIPPED_DIAGONALLY: u32 = 0x2000_0000;
+const ROTATED_HEXAGONAL_120: u32 = 0x1000_0000;
+const FLAGS: u32 =
+    FLIPPED_HORIZONTALLY | FLIPPED_VERTICALLY | FLIPPED_DIAGONALLY | ROTATED_HEXAGONAL_120;
+
+#[derive(Deserialize)]
+struct Map {
+    width: usize,
+    height: usize,
+    tilewidth: i32,
+    tileheight: i32,
+    #[serde(default)]
+    infinite: bool,
+    layers: Vec<Layer>,
+    #[serde(default)]
+    tilesets: Vec<Tileset>,
+}
+
+#[derive(Deserialize)]
+struct Tileset {
+    firstgid: u32,
+    /// Set for tilesets saved in their own file.
+    #[serde(default)]
+    source: Option<String>,
+    /// Set for tilesets embedded in the map.
+    #[serde(default)]
+    image: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Layer {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    class: String,
+    #[serde(default)]
+    width: usize,
+    #[serde(default)]
+    height: usize,
+    #[serde(default)]
+    offsetx: f32,
+    #[serde(default)]
+    offsety: f32,
+    #[serde(default = "visible")]
+    visible: bool,
+    #[serde(default)]
+    encoding: Option<String>,
+    #[serde(default)]
+    data: Option<Value>,
+    #[serde(default)]
+    objects: Vec<Object>,
+    /// Children of group layers.
+    #[serde(default)]
+    layers: Vec<Layer>,
+}
+
+fn visible() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+struct Object {
+    id: u32,
+    #[serde(default)]
+    name: String,
+    /// Called "class" in the editor since Tiled 1.9, and saved as either key depending on
+    /// the version; see `Object::kind`.
+    #[serde(default, rename = "type")]
+    type_: Option<String>,
+    #[serde(default)]
+    class: Option<String>,
+    x: f32,
+    y: f32,
+    #[serde(default)]
+    width: f32,
+    #[serde(default)]
+    height: f32,
+    #[serde(default)]
+    properties: Vec<Property>,
+}
+
+impl Object {
+    /// The object's class, preferring "class" when a file has both keys.
+    fn kind(&self) -> &str {
+        self.class
+            .as_deref()
+            .or(self.type_.as_deref())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Deserialize)]
+struct Property {
+    name: String,
+    #[serde(rename = "type", default)]
+    kind: String,
+    value: Value,
+}
+
+/// Parses the JSON text of a `.tmj` file.
+pub fn parse(json: &[u8]) -> Result<Level, Error> {
+    let map: Map = serde_json::from_slice(json).map_err(|err| anyhow!("{}", err))?;
+    ensure!(!map.infinite, "infinite Tiled maps aren't supported");
+
+    let mut tilesets = map.tilesets;
+    tilesets.sort_by_key(|tileset| tileset.firstgid);
+    let mut level = Level {
+        name: String::new(),
+        size: ScreenSize::new(
+            map.width as i32 * map.tilewidth,
+            map.height as i32 * map.tileheight,
+        ),
+        world_offset: ScreenVector::zero(),
+        tile_layers: Vec::new(),
+        int_grid_layers: Vec::new(),
+        object_layers: Vec::new(),
+        collision: Vec::new(),
+    };
+    let tile_size = ScreenSize::new(map.tilewidth, map.tileheight);
+    add_layers(
+        &mut level,
+        &map.layers,
+        &tilesets,
+        tile_size,
+        (0.0, 0.0),
+        true,
+    )?;
+    Ok(level)
+}
+
+fn add_layers(
+    level: &mut Level,
+    layers: &[Layer],
+    tilesets: &[Tileset],
+    tile_size: ScreenSize,
+    parent_offset: (f32, f32),
+    parent_visible: bool,
+) -> Result<(), Error> {
+    for layer in layers {
+        let offset = (
+            parent_offset.0 + layer.offsetx,
+            parent_offset.1 + layer.offsety,
+        );
+        let visible = parent_visible && layer.visible;
+        match layer.kind.as_str() {
+            "tilelayer" => {
+                level
+                    .tile_layers
+                    .push(tile_layer(layer, tilesets, tile_size, offset, visible)?);
+            }
+            "objectgroup" => {
+                let collision_layer = is_collision(&layer.name) || is_collision(&layer.class);
+                let mut entities = Vec::with_capacity(layer.objects.len());
+                for object in &layer.objects {
+                    let rect = GrRect::new(
+                        (object.x + offset.0, object.y + offset.1).into(),
+                        (object.width, object.height).into(),
+                    );
+                    if (collision_layer || is_collision(object.kind())) && !rect.is_empty() {
+                        level.collision.push(rect);
+                    }
+                    entities.push(Entity {
+                        id: format!("{}", object.id),
+                        name: object.name.clone(),
+                        kind: object.kind().into(),
+                        rect,
+                        properties: properties(&object.properties),
+                    });
+                }
+                level.object_layers.push(ObjectLayer {
+                    name: layer.name.clone(),
+                    offset: ScreenVector::new(offset.0 as i32, offset.1 as i32),
+                    visible,
+                    entities,
+                });
+            }
+            "group" => add_layers(level, &layer.layers, tilesets, tile_size, offset, visible)?,
+            // Image layers have nothing to load.
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn tile_layer(
+    layer: &Layer,
+    tilesets: &[Tileset],
+    tile_size: ScreenSize,
+    offset: (f32, f32),
+    visible: bool,
+) -> Result<TileLayer, Error> {
+    // Tile data is an array of ids unless the map was saved with base64 tile layers.
+    let data = match &layer.data {
+        Some(Value::Array(ids)) => ids
+            .iter()
+            .map(|id| id.as_u64().map(|id| id as u32))
+            .collect::<Option<Vec<u32>>>()
+            .ok_or_else(|| anyhow!("layer {} has a tile id that isn't a number", layer.name))?,
+        Some(_) => bail!(
+            "layer {} is {} encoded; save the map with the CSV tile layer format",
+            layer.name,
+            layer.encoding.as_deref().unwrap_or("base64")
+        ),
+        None => bail!("layer {} has no tile data", layer.name),
+    };
+    ensure!(
+        data.len() == layer.width * layer.height,
+        "layer {} has {} tiles, expected {}",
+        layer.name,
+        data.len(),
+        layer.width * layer.height
+    );
+
+    // A tilemap draws from a single table, so use the tileset of the first tile.
+    let tileset = data
+        .iter()
+        .find(|&&gid| gid & !FLAGS != 0)
+        .and_then(|&gid| tileset_for(tilesets, gid & !FLAGS));
+    let mut tiles = Vec::with_capacity(data.len());
+    for &gid in &data {
+        let id = gid & !FLAGS;
+        if id == 0 {
+            tiles.push(None);
+            continue;
+        }
+        let first_gid = tileset_for(tilesets, id)
+            .map(|tileset| tileset.firstgid)
+            .unwrap_or(1);
+        if gid & FLIPPED_DIAGONALLY != 0 {
+            bail!(
+                "layer {} has a rotated tile, which can't be drawn; use flips only",
+                layer.name
+            );
+        }
+        let flip = match (
+            gid & FLIPPED_HORIZONTALLY != 0,
+            gid & FLIPPED_VERTICALLY != 0,
+        ) {
+            (false, false) => LCDBitmapFlip::kBitmapUnflipped,
+            (true, false) => LCDBitmapFlip::kBitmapFlippedX,
+            (false, true) => LCDBitmapFlip::kBitmapFlippedY,
+            (true, true) => LCDBitmapFlip::kBitmapFlippedXY,
+        };
+        tiles.push(Some(Tile::flipped((id - first_gid) as usize, flip)));
+    }
+
+    Ok(TileLayer {
+        name: layer.name.clone(),
+        width: layer.width,
+        height: layer.height,
+        tile_size,
+        offset: ScreenVector::new(offset.0 as i32, offset.1 as i32),
+        visible,
+        tileset: tileset.and_then(|tileset| tileset.image.clone().or(tileset.source.clone())),
+        tiles,
+    })
+}
+
+/// Finds the tileset holding tile `id`, the one with the highest first id not above it.
+fn tileset_for(tilesets: &[Tileset], id: u32) -> Option<&Tileset> {
+    tilesets.iter().rev().find(|tileset| tileset.firstgid <= id)
+}
+
+fn is_collision(name: &str) -> bool {
+    name.eq_ignore_ascii_case("collision")
+}
+
+fn properties(properties: &[Property]) -> HashMap<String, PropertyValue> {
+    properties
+        .iter()
+        .filter_map(|property| {
+            let value = match (property.kind.as_str(), &property.value) {
+                ("bool", Value::Bool(value)) => PropertyValue::Bool(*value),
+                ("int" | "object", Value::Number(value)) => {
+                    PropertyValue::Int(value.as_i64().unwrap_or_default())
+                }
+                ("float", Value::Number(value)) => {
+                    PropertyValue::Float(value.as_f64().unwrap_or_default() as f32)
+                }
+                (_, Value::String(value)) => PropertyValue::String(value.clone()),
+                // Class properties hold nested objects, which aren't supported.
+                _ => return None,
+            };
+            Some((property.name.clone(), value))
+        })
+        .collect()
+}
@@ -10,6 +10,8 @@ pub mod display;
 pub mod file;
 pub mod geometry;
 pub mod graphics;
+#[cfg(feature = "levels")]
+pub mod levels;
 pub mod lua;
 pub mod sound;
 pub mod sprite;
@@ -150,6 +152,8 @@ impl<T: 'static + Game> GameRunner<T> {
             return;
         }
 
+        sound::callback::run_due_callbacks();
+
         if let Some(game) = self.game.as_mut() {
             if let Err(err) = game.update(&mut self.playdate) {
                 log_to_console!("Error in update: {err:#}")
@@ -25,10 +25,37 @@ use anyhow::{anyhow, ensure, Error, Result};
 use core::ptr;
 use cstr_core::CString;
 
+pub(crate) mod callback;
 pub mod sampleplayer;
-pub use sampleplayer::{AudioSample, SamplePlayer};
+pub use sampleplayer::{AudioSample, SampleInfo, SamplePlayer, SoundFormat};
 pub mod fileplayer;
 pub use fileplayer::FilePlayer;
+pub mod channel;
+pub use channel::Channel;
+pub mod effect;
+pub use effect::{
+    AudioEffect, BitCrusher, CustomEffect, DelayLine, DelayLineTap, OnePoleFilter, Overdrive,
+    RingModulator, SoundEffect, TwoPoleFilter, TwoPoleFilterType,
+};
+pub mod envelope;
+pub use envelope::Envelope;
+pub mod generator;
+pub use generator::{AudioGenerator, GeneratorSource};
+pub mod instrument;
+pub use instrument::Instrument;
+pub mod lfo;
+pub use lfo::{LFOType, Lfo};
+pub mod microphone;
+pub use microphone::{HeadphoneState, MicListener, MicSource, Microphone, Recorder};
+pub mod sequence;
+pub use sequence::{NoteEvent, Sequence, Track};
+pub mod signal;
+pub use signal::{ControlSignal, CustomSignal, Modulator, SignalSource};
+pub mod source;
+pub use source::SoundSource;
+pub mod synth;
+pub use synth::{Adsr, SoundWaveform, Synth};
+pub mod wav;
 
 // When the Playdate system struct is created, it passes the given playdate_sound to Sound::new,
 // which then replaces this.
@@ -44,10 +71,20 @@ pub struct Sound {
     raw_file_player: *const crankstart_sys::playdate_sound_fileplayer,
     raw_sample: *const crankstart_sys::playdate_sound_sample,
     raw_sample_player: *const crankstart_sys::playdate_sound_sampleplayer,
+    raw_synth: *const crankstart_sys::playdate_sound_synth,
+    raw_sequence: *const crankstart_sys::playdate_sound_sequence,
+    raw_track: *const crankstart_sys::playdate_sound_track,
+    raw_instrument: *const crankstart_sys::playdate_sound_instrument,
+    raw_channel: *const crankstart_sys::playdate_sound_channel,
+    raw_effect: *const crankstart_sys::playdate_sound_effect,
+    raw_lfo: *const crankstart_sys::playdate_sound_lfo,
+    raw_envelope: *const crankstart_sys::playdate_sound_envelope,
+    raw_control_signal: *const crankstart_sys::playdate_control_signal,
+    raw_signal: *const crankstart_sys::playdate_sound_signal,
 }
 
-// Not implemented: addSource, removeSource, setMicCallback, and getHeadphoneState (waiting on
-// crankstart callback strategy), getDefaultChannel, addChannel, removeChannel.
+// Channels are added and removed by `Channel`, sources by `add_generator`, and the microphone
+// and headphone state are in the `microphone` module.
 impl Sound {
     const fn null() -> Self {
         Self {
@@ -55,6 +92,16 @@ impl Sound {
             raw_file_player: ptr::null(),
             raw_sample: ptr::null(),
             raw_sample_player: ptr::null(),
+            raw_synth: ptr::null(),
+            raw_sequence: ptr::null(),
+            raw_track: ptr::null(),
+            raw_instrument: ptr::null(),
+            raw_channel: ptr::null(),
+            raw_effect: ptr::null(),
+            raw_lfo: ptr::null(),
+            raw_envelope: ptr::null(),
+            raw_control_signal: ptr::null(),
+            raw_signal: ptr::null(),
         }
     }
 
@@ -70,12 +117,42 @@ impl Sound {
         ensure!(!raw_sample.is_null(), "Null sound.sample");
         let raw_sample_player = unsafe { (*raw_sound).sampleplayer };
         ensure!(!raw_sample_player.is_null(), "Null sound.sampleplayer");
+        let raw_synth = unsafe { (*raw_sound).synth };
+        ensure!(!raw_synth.is_null(), "Null sound.synth");
+        let raw_sequence = unsafe { (*raw_sound).sequence };
+        ensure!(!raw_sequence.is_null(), "Null sound.sequence");
+        let raw_track = unsafe { (*raw_sound).track };
+        ensure!(!raw_track.is_null(), "Null sound.track");
+        let raw_instrument = unsafe { (*raw_sound).instrument };
+        ensure!(!raw_instrument.is_null(), "Null sound.instrument");
+        let raw_channel = unsafe { (*raw_sound).channel };
+        ensure!(!raw_channel.is_null(), "Null sound.channel");
+        let raw_effect = unsafe { (*raw_sound).effect };
+        ensure!(!raw_effect.is_null(), "Null sound.effect");
+        let raw_lfo = unsafe { (*raw_sound).lfo };
+        ensure!(!raw_lfo.is_null(), "Null sound.lfo");
+        let raw_envelope = unsafe { (*raw_sound).envelope };
+        ensure!(!raw_envelope.is_null(), "Null sound.envelope");
+        let raw_control_signal = unsafe { (*raw_sound).controlsignal };
+        ensure!(!raw_control_signal.is_null(), "Null sound.controlsignal");
+        let raw_signal = unsafe { (*raw_sound).signal };
+        ensure!(!raw_signal.is_null(), "Null sound.signal");
 
         let sound = Self {
             raw_sound,
             raw_file_player,
             raw_sample,
             raw_sample_player,
+            raw_synth,
+            raw_sequence,
+            raw_track,
+            raw_instrument,
+            raw_channel,
+            raw_effect,
+            raw_lfo,
+            raw_envelope,
+            raw_control_signal,
+            raw_signal,
         };
         unsafe { SOUND = sound };
         Ok(())
@@ -0,0 +1,98 @@
+//! Closures called back by the sound engine, e.g. when a player finishes.
+//!
+//! The engine can make these calls from the audio thread, where Rust closures can't safely
+//! run, so the C callback only marks the closure as due.  Due closures are run on the main
+//! thread at the start of the next update, before `Game::update`.  A closure that fires more
+//! than once between updates is run once.
+
+use crankstart_sys::ctypes;
+
+use alloc::{
+    boxed::Box,
+    rc::{Rc, Weak},
+    vec::Vec,
+};
+use core::{
+    cell::RefCell,
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+// Every callback that's been registered and not yet dropped.  Only touched on the main thread.
+static mut CALLBACKS: Vec<Weak<CallbackInner>> = Vec::new();
+
+struct CallbackInner {
+    due: AtomicBool,
+    closure: RefCell<Box<dyn FnMut()>>,
+}
+
+/// A closure the sound engine can call back.  Pass `SoundCallback::proc` and `userdata` to
+/// the C API, and hold on to this until the engine has been given another callback, or the
+/// object it was given to has been freed.
+pub(crate) struct SoundCallback {
+    inner: Rc<CallbackInner>,
+}
+
+impl SoundCallback {
+    pub(crate) fn new(closure: impl FnMut() + 'static) -> Self {
+        let inner = Rc::new(CallbackInner {
+            due: AtomicBool::new(false),
+            closure: RefCell::new(Box::new(closure)),
+        });
+        unsafe { CALLBACKS.push(Rc::downgrade(&inner)) };
+        Self { inner }
+    }
+
+    /// Wraps a closure that's only called once, such as when a fade finishes.
+    pub(crate) fn once(closure: impl FnOnce() + 'static) -> Self {
+        let mut closure = Some(closure);
+        Self::new(move || {
+            if let Some(closure) = closure.take() {
+                closure();
+            }
+        })
+    }
+
+    pub(crate) fn userdata(&self) -> *mut ctypes::c_void {
+        Rc::as_ptr(&self.inner) as *mut ctypes::c_void
+    }
+
+    /// The C callback, for any of the engine's callback types that pass an object and the
+    /// userdata.
+    pub(crate) extern "C" fn proc<T>(_object: *mut T, userdata: *mut ctypes::c_void) {
+        if userdata.is_null() {
+            return;
+        }
+        let inner = unsafe { &*(userdata as *const CallbackInner) };
+        inner.due.store(true, Ordering::Release);
+    }
+}
+
+impl fmt::Debug for SoundCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SoundCallback")
+            .field("due", &self.inner.due)
+            .finish()
+    }
+}
+
+/// Runs the closures whose callbacks fired since the last update, and forgets dropped ones.
+pub(crate) fn run_due_callbacks() {
+    let callbacks = unsafe { &mut CALLBACKS };
+    let mut due = Vec::new();
+    callbacks.retain(|callback| match callback.upgrade() {
+        Some(callback) => {
+            if callback.due.swap(false, Ordering::Acquire) {
+                due.push(callback);
+            }
+            true
+        }
+        None => false,
+    });
+    // Run them after collecting, since closures can register new callbacks.
+    for callback in due {
+        if let Ok(mut closure) = callback.closure.try_borrow_mut() {
+            closure();
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use super::{
+    effect::SoundEffect,
+    signal::{modulator_parts, Modulator},
+    source::SoundSource,
+    Sound,
+};
+use crate::{pd_func_caller, pd_func_caller_log};
+use crankstart_sys::ctypes;
+
+use alloc::{boxed::Box, vec::Vec};
+use anyhow::{anyhow, ensure, Error, Result};
+use core::ptr;
+
+/// A bus that mixes sound sources, runs them through a chain of effects and feeds the result
+/// to the output.
+///
+/// For example, to send music and sound effects through separate channels, and muffle the
+/// music while the game is paused:
+///
+/// ```ignore
+/// let mut music_channel = Channel::new()?;
+/// music_channel.add_source(&music)?;
+/// let sfx_channel = Channel::new()?;
+/// sfx_channel.add_source(&explosion_player)?;
+///
+/// let muffle = TwoPoleFilter::new()?;
+/// muffle.set_type(TwoPoleFilterType::kFilterTypeLowPass)?;
+/// muffle.set_frequency(600.0)?;
+///
+/// // On pause:
+/// music_channel.add_effect(&muffle)?;
+/// // On resume:
+/// music_channel.remove_effect(&muffle)?;
+/// ```
+#[derive(Debug)]
+pub struct Channel {
+    raw_subsystem: *const crankstart_sys::playdate_sound_channel,
+    raw_channel: *mut crankstart_sys::SoundChannel,
+    // Whether this is one of our channels, rather than the system's default channel.
+    owned: bool,
+
+    // The effects and modulators in use, held so they're not freed while the channel uses them.
+    effects: Vec<Box<dyn SoundEffect>>,
+    volume_modulator: Option<Box<dyn Modulator>>,
+    pan_modulator: Option<Box<dyn Modulator>>,
+}
+
+impl Drop for Channel {
+    fn drop(&mut self) {
+        if self.owned {
+            // Use _log to leak rather than fail
+            pd_func_caller_log!((*Sound::get().raw_sound).removeChannel, self.raw_channel);
+            pd_func_caller_log!((*self.raw_subsystem).freeChannel, self.raw_channel);
+        } else {
+            // The default channel outlives us, so take back the effects and modulators we're
+            // about to drop.
+            for effect in &self.effects {
+                pd_func_caller_log!(
+                    (*self.raw_subsystem).removeEffect,
+                    self.raw_channel,
+                    effect.raw_effect()
+                );
+            }
+            if self.volume_modulator.is_some() {
+                pd_func_caller_log!(
+                    (*self.raw_subsystem).setVolumeModulator,
+                    self.raw_channel,
+                    ptr::null_mut()
+                );
+            }
+            if self.pan_modulator.is_some() {
+                pd_func_caller_log!(
+                    (*self.raw_subsystem).setPanModulator,
+                    self.raw_channel,
+                    ptr::null_mut()
+                );
+            }
+        }
+    }
+}
+
+// Not implemented: getDryLevelSignal and getWetLevelSignal.
+impl Channel {
+    /// Creates a channel and adds it to the sound engine's output.  It's removed and freed
+    /// when dropped.
+    pub fn new() -> Result<Self> {
+        let sound = Sound::get();
+        let raw_channel = pd_func_caller!((*sound.raw_channel).newChannel)?;
+        ensure!(
+            !raw_channel.is_null(),
+            "Null returned from channel.newChannel"
+        );
+        let channel = Self::from_raw(sound.raw_channel, raw_channel, true);
+        pd_func_caller!((*sound.raw_sound).addChannel, raw_channel)?;
+        Ok(channel)
+    }
+
+    /// Returns the system's default channel, which plays every source not added to another
+    /// channel.
+    pub fn default_channel() -> Result<Self> {
+        let sound = Sound::get();
+        let raw_channel = pd_func_caller!((*sound.raw_sound).getDefaultChannel)?;
+        ensure!(
+            !raw_channel.is_null(),
+            "Null returned from sound.getDefaultChannel"
+        );
+        Ok(Self::from_raw(sound.raw_channel, raw_channel, false))
+    }
+
+    fn from_raw(
+        raw_subsystem: *const crankstart_sys::playdate_sound_channel,
+        raw_channel: *mut crankstart_sys::SoundChannel,
+        owned: bool,
+    ) -> Self {
+        Self {
+            raw_subsystem,
+            raw_channel,
+            owned,
+            effects: Vec::new(),
+            volume_modulator: None,
+            pan_modulator: None,
+        }
+    }
+
+    pub(crate) fn raw_channel(&self) -> *mut crankstart_sys::SoundChannel {
+        self.raw_channel
+    }
+
+    /// Plays `source` through this channel instead of the one it was in.  The source must
+    /// outlive its time in the channel; remove it before dropping it.
+    pub fn add_source(&self, source: &dyn SoundSource) -> Result<()> {
+        pd_func_caller!(
+            (*self.raw_subsystem).addSource,
+            self.raw_channel,
+            source.raw_source()
+        )?;
+        Ok(())
+    }
+
+    /// Removes `source`, returning whether it was in the channel.
+    pub fn remove_source(&self, source: &dyn SoundSource) -> Result<bool> {
+        let result = pd_func_caller!(
+            (*self.raw_subsystem).removeSource,
+            self.raw_channel,
+            source.raw_source()
+        )?;
+        Ok(result == 1)
+    }
+
+    /// Adds `effect` to the end of the channel's effect chain.
+    pub fn add_effect(&mut self, effect: &dyn SoundEffect) -> Result<()> {
+        pd_func_caller!(
+            (*self.raw_subsystem).addEffect,
+            self.raw_channel,
+            effect.raw_effect()
+        )?;
+        self.effects.push(effect.clone_effect());
+        Ok(())
+    }
+
+    pub fn remove_effect(&mut self, effect: &dyn SoundEffect) -> Result<()> {
+        let raw_effect = effect.raw_effect();
+        pd_func_caller!(
+            (*self.raw_subsystem).removeEffect,
+            self.raw_channel,
+            raw_effect
+        )?;
+        self.effects.retain(|held| held.raw_effect() != raw_effect);
+        Ok(())
+    }
+
+    /// Gets the channel's volume, out of 1.
+    pub fn get_volume(&self) -> Result<f32> {
+        pd_func_caller!((*self.raw_subsystem).getVolume, self.raw_channel)
+    }
+
+    /// Sets the channel's volume, out of 1.
+    pub fn set_volume(&self, volume: f32) -> Result<()> {
+        pd_func_caller!((*self.raw_subsystem).setVolume, self.raw_channel, volume)
+    }
+
+    /// Modulates the volume, or stops modulating it with None.
+    pub fn set_volume_modulator(&mut self, modulator: Option<&dyn Modulator>) -> Result<()> {
+        let (raw_signal, modulator) = modulator_parts(modulator);
+        pd_func_caller!(
+            (*self.raw_subsystem).setVolumeModulator,
+            self.raw_channel,
+            raw_signal
+        )?;
+        self.volume_modulator = modulator;
+        Ok(())
+    }
+
+    /// Sets where the channel sits between the speakers, from -1 (left) to 1 (right).
+    pub fn set_pan(&self, pan: f32) -> Result<()> {
+        pd_func_caller!((*self.raw_subsystem).setPan, self.raw_channel, pan)
+    }
+
+    /// Modulates the pan, or stops modulating it with None.
+    pub fn set_pan_modulator(&mut self, modulator: Option<&dyn Modulator>) -> Result<()> {
+        let (raw_signal, modulator) = modulator_parts(modulator);
+        pd_func_caller!(
+            (*self.raw_subsystem).setPanModulator,
+            self.raw_channel,
+            raw_signal
+        )?;
+        self.pan_modulator = modulator;
+        Ok(())
+    }
+}
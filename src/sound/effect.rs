@@ -0,0 +1,640 @@
+//! Effects that can be added to a `Channel`: the built-in ones, and `CustomEffect`s that
+//! process audio in Rust.
+//!
+//! Effects are handles, so clones refer to the same effect; a channel holds on to the effects
+//! added to it.
+
+use super::{
+    signal::{modulator_parts, Modulator},
+    source::SoundSource,
+    Sound,
+};
+use crate::{pd_func_caller, pd_func_caller_log};
+use crankstart_sys::ctypes;
+
+use alloc::{boxed::Box, rc::Rc, vec::Vec};
+use anyhow::{anyhow, ensure, Error, Result};
+use core::{
+    cell::{RefCell, UnsafeCell},
+    fmt, slice,
+};
+
+pub use crankstart_sys::TwoPoleFilterType;
+
+/// An effect that can be added to a `Channel`.
+pub trait SoundEffect: fmt::Debug {
+    /// Returns the effect to pass to the C API.
+    fn raw_effect(&self) -> *mut crankstart_sys::SoundEffect;
+
+    /// Returns another handle to the same effect.
+    fn clone_effect(&self) -> Box<dyn SoundEffect>;
+
+    /// Sets how much of the effect is heard, from 0 (only the unaffected signal) to 1 (only
+    /// the effect's output).
+    fn set_mix(&self, level: f32) -> Result<()> {
+        pd_func_caller!((*Sound::get().raw_effect).setMix, self.raw_effect(), level)
+    }
+
+    /// Modulates the mix level, or stops modulating it with None.
+    fn set_mix_modulator(&self, modulator: Option<&dyn Modulator>) -> Result<()>;
+}
+
+/// An effect and the modulators it's using, freed when the last handle is dropped.
+struct EffectInner<T> {
+    raw: *mut T,
+    free: Option<unsafe extern "C" fn(*mut T)>,
+    modulators: RefCell<Vec<(&'static str, Box<dyn Modulator>)>>,
+}
+
+impl<T> EffectInner<T> {
+    fn new(raw: *mut T, free: Option<unsafe extern "C" fn(*mut T)>) -> Result<Rc<Self>> {
+        ensure!(!raw.is_null(), "Null pointer returned creating effect");
+        Ok(Rc::new(Self {
+            raw,
+            free,
+            modulators: RefCell::new(Vec::new()),
+        }))
+    }
+
+    /// Holds on to the modulator for `parameter`, dropping the one it replaces.
+    fn hold(&self, parameter: &'static str, modulator: Option<Box<dyn Modulator>>) {
+        let mut modulators = self.modulators.borrow_mut();
+        modulators.retain(|(held, _)| *held != parameter);
+        if let Some(modulator) = modulator {
+            modulators.push((parameter, modulator));
+        }
+    }
+
+    fn set_mix_modulator(&self, modulator: Option<&dyn Modulator>) -> Result<()> {
+        let (raw_signal, modulator) = modulator_parts(modulator);
+        pd_func_caller!(
+            (*effect_subsystem()).setMixModulator,
+            self.raw as *mut crankstart_sys::SoundEffect,
+            raw_signal
+        )?;
+        self.hold("mix", modulator);
+        Ok(())
+    }
+}
+
+impl<T> Drop for EffectInner<T> {
+    fn drop(&mut self) {
+        // Use _log to leak rather than fail
+        pd_func_caller_log!(self.free, self.raw);
+    }
+}
+
+impl<T> fmt::Debug for EffectInner<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EffectInner")
+            .field("raw", &self.raw)
+            .field("modulators", &self.modulators)
+            .finish()
+    }
+}
+
+fn effect_subsystem() -> *const crankstart_sys::playdate_sound_effect {
+    Sound::get().raw_effect
+}
+
+/// A resonant filter: low pass, high pass, band pass, notch, peaking EQ or shelving.
+#[derive(Clone, Debug)]
+pub struct TwoPoleFilter {
+    inner: Rc<EffectInner<crankstart_sys::TwoPoleFilter>>,
+}
+
+impl TwoPoleFilter {
+    /// Creates a low pass filter.
+    pub fn new() -> Result<Self> {
+        let subsystem = Self::subsystem();
+        let raw = pd_func_caller!((*subsystem).newFilter)?;
+        Ok(Self {
+            inner: EffectInner::new(raw, unsafe { (*subsystem).freeFilter })?,
+        })
+    }
+
+    fn subsystem() -> *const crankstart_sys::playdate_sound_effect_twopolefilter {
+        unsafe { (*effect_subsystem()).twopolefilter }
+    }
+
+    pub fn set_type(&self, filter_type: TwoPoleFilterType) -> Result<()> {
+        pd_func_caller!((*Self::subsystem()).setType, self.inner.raw, filter_type)
+    }
+
+    /// Sets the cutoff or center frequency, in Hz.
+    pub fn set_frequency(&self, frequency: f32) -> Result<()> {
+        pd_func_caller!((*Self::subsystem()).setFrequency, self.inner.raw, frequency)
+    }
+
+    pub fn set_frequency_modulator(&self, modulator: Option<&dyn Modulator>) -> Result<()> {
+        let (raw_signal, modulator) = modulator_parts(modulator);
+        pd_func_caller!(
+            (*Self::subsystem()).setFrequencyModulator,
+            self.inner.raw,
+            raw_signal
+        )?;
+        self.inner.hold("frequency", modulator);
+        Ok(())
+    }
+
+    /// Sets the gain, in dB, of the peaking EQ and shelving filters.
+    pub fn set_gain(&self, gain: f32) -> Result<()> {
+        pd_func_caller!((*Self::subsystem()).setGain, self.inner.raw, gain)
+    }
+
+    /// Sets the resonance, from 0 to 1.
+    pub fn set_resonance(&self, resonance: f32) -> Result<()> {
+        pd_func_caller!((*Self::subsystem()).setResonance, self.inner.raw, resonance)
+    }
+
+    pub fn set_resonance_modulator(&self, modulator: Option<&dyn Modulator>) -> Result<()> {
+        let (raw_signal, modulator) = modulator_parts(modulator);
+        pd_func_caller!(
+            (*Self::subsystem()).setResonanceModulator,
+            self.inner.raw,
+            raw_signal
+        )?;
+        self.inner.hold("resonance", modulator);
+        Ok(())
+    }
+}
+
+impl SoundEffect for TwoPoleFilter {
+    fn raw_effect(&self) -> *mut crankstart_sys::SoundEffect {
+        self.inner.raw as *mut crankstart_sys::SoundEffect
+    }
+
+    fn clone_effect(&self) -> Box<dyn SoundEffect> {
+        Box::new(self.clone())
+    }
+
+    fn set_mix_modulator(&self, modulator: Option<&dyn Modulator>) -> Result<()> {
+        self.inner.set_mix_modulator(modulator)
+    }
+}
+
+/// A simple, cheap filter that's low pass or high pass depending on its parameter.
+#[derive(Clone, Debug)]
+pub struct OnePoleFilter {
+    inner: Rc<EffectInner<crankstart_sys::OnePoleFilter>>,
+}
+
+impl OnePoleFilter {
+    pub fn new() -> Result<Self> {
+        let subsystem = Self::subsystem();
+        let raw = pd_func_caller!((*subsystem).newFilter)?;
+        Ok(Self {
+            inner: EffectInner::new(raw, unsafe { (*subsystem).freeFilter })?,
+        })
+    }
+
+    fn subsystem() -> *const crankstart_sys::playdate_sound_effect_onepolefilter {
+        unsafe { (*effect_subsystem()).onepolefilter }
+    }
+
+    /// Sets the filter's cutoff, from -1 (high pass) through 0 (no filtering) to 1 (low pass).
+    pub fn set_parameter(&self, parameter: f32) -> Result<()> {
+        pd_func_caller!((*Self::subsystem()).setParameter, self.inner.raw, parameter)
+    }
+
+    pub fn set_parameter_modulator(&self, modulator: Option<&dyn Modulator>) -> Result<()> {
+        let (raw_signal, modulator) = modulator_parts(modulator);
+        pd_func_caller!(
+            (*Self::subsystem()).setParameterModulator,
+            self.inner.raw,
+            raw_signal
+        )?;
+        self.inner.hold("parameter", modulator);
+        Ok(())
+    }
+}
+
+impl SoundEffect for OnePoleFilter {
+    fn raw_effect(&self) -> *mut crankstart_sys::SoundEffect {
+        self.inner.raw as *mut crankstart_sys::SoundEffect
+    }
+
+    fn clone_effect(&self) -> Box<dyn SoundEffect> {
+        Box::new(self.clone())
+    }
+
+    fn set_mix_modulator(&self, modulator: Option<&dyn Modulator>) -> Result<()> {
+        self.inner.set_mix_modulator(modulator)
+    }
+}
+
+/// Reduces the bit depth and sample rate for a lo-fi sound.
+#[derive(Clone, Debug)]
+pub struct BitCrusher {
+    inner: Rc<EffectInner<crankstart_sys::BitCrusher>>,
+}
+
+impl BitCrusher {
+    pub fn new() -> Result<Self> {
+        let subsystem = Self::subsystem();
+        let raw = pd_func_caller!((*subsystem).newBitCrusher)?;
+        Ok(Self {
+            inner: EffectInner::new(raw, unsafe { (*subsystem).freeBitCrusher })?,
+        })
+    }
+
+    fn subsystem() -> *const crankstart_sys::playdate_sound_effect_bitcrusher {
+        unsafe { (*effect_subsystem()).bitcrusher }
+    }
+
+    /// Sets how much to reduce the bit depth, from 0 (none) to 1 (all the way).
+    pub fn set_amount(&self, amount: f32) -> Result<()> {
+        pd_func_caller!((*Self::subsystem()).setAmount, self.inner.raw, amount)
+    }
+
+    pub fn set_amount_modulator(&self, modulator: Option<&dyn Modulator>) -> Result<()> {
+        let (raw_signal, modulator) = modulator_parts(modulator);
+        pd_func_caller!(
+            (*Self::subsystem()).setAmountModulator,
+            self.inner.raw,
+            raw_signal
+        )?;
+        self.inner.hold("amount", modulator);
+        Ok(())
+    }
+
+    /// Sets how much to reduce the sample rate, from 0 (none) to 1 (all the way).
+    pub fn set_undersampling(&self, undersampling: f32) -> Result<()> {
+        pd_func_caller!(
+            (*Self::subsystem()).setUndersampling,
+            self.inner.raw,
+            undersampling
+        )
+    }
+
+    pub fn set_undersample_modulator(&self, modulator: Option<&dyn Modulator>) -> Result<()> {
+        let (raw_signal, modulator) = modulator_parts(modulator);
+        pd_func_caller!(
+            (*Self::subsystem()).setUndersampleModulator,
+            self.inner.raw,
+            raw_signal
+        )?;
+        self.inner.hold("undersampling", modulator);
+        Ok(())
+    }
+}
+
+impl SoundEffect for BitCrusher {
+    fn raw_effect(&self) -> *mut crankstart_sys::SoundEffect {
+        self.inner.raw as *mut crankstart_sys::SoundEffect
+    }
+
+    fn clone_effect(&self) -> Box<dyn SoundEffect> {
+        Box::new(self.clone())
+    }
+
+    fn set_mix_modulator(&self, modulator: Option<&dyn Modulator>) -> Result<()> {
+        self.inner.set_mix_modulator(modulator)
+    }
+}
+
+/// Multiplies the signal by a sine wave, for metallic and robotic sounds.
+#[derive(Clone, Debug)]
+pub struct RingModulator {
+    inner: Rc<EffectInner<crankstart_sys::RingModulator>>,
+}
+
+impl RingModulator {
+    pub fn new() -> Result<Self> {
+        let subsystem = Self::subsystem();
+        let raw = pd_func_caller!((*subsystem).newRingmod)?;
+        Ok(Self {
+            inner: EffectInner::new(raw, unsafe { (*subsystem).freeRingmod })?,
+        })
+    }
+
+    fn subsystem() -> *const crankstart_sys::playdate_sound_effect_ringmodulator {
+        unsafe { (*effect_subsystem()).ringmodulator }
+    }
+
+    /// Sets the frequency of the sine wave, in Hz.
+    pub fn set_frequency(&self, frequency: f32) -> Result<()> {
+        pd_func_caller!((*Self::subsystem()).setFrequency, self.inner.raw, frequency)
+    }
+
+    pub fn set_frequency_modulator(&self, modulator: Option<&dyn Modulator>) -> Result<()> {
+        let (raw_signal, modulator) = modulator_parts(modulator);
+        pd_func_caller!(
+            (*Self::subsystem()).setFrequencyModulator,
+            self.inner.raw,
+            raw_signal
+        )?;
+        self.inner.hold("frequency", modulator);
+        Ok(())
+    }
+}
+
+impl SoundEffect for RingModulator {
+    fn raw_effect(&self) -> *mut crankstart_sys::SoundEffect {
+        self.inner.raw as *mut crankstart_sys::SoundEffect
+    }
+
+    fn clone_effect(&self) -> Box<dyn SoundEffect> {
+        Box::new(self.clone())
+    }
+
+    fn set_mix_modulator(&self, modulator: Option<&dyn Modulator>) -> Result<()> {
+        self.inner.set_mix_modulator(modulator)
+    }
+}
+
+/// An echo.  The delay line's own output is the signal from its full length; taps read it from
+/// shorter delays and are added to a channel as sources.
+///
+/// ```ignore
+/// // Half a second of delay with a quarter second tap, at 44.1k frames per second.
+/// let echo = DelayLine::new(22050, false)?;
+/// echo.set_feedback(0.4)?;
+/// let tap = echo.add_tap(11025)?;
+/// channel.add_effect(&echo)?;
+/// channel.add_source(&tap)?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct DelayLine {
+    inner: Rc<EffectInner<crankstart_sys::DelayLine>>,
+}
+
+impl DelayLine {
+    /// Creates a delay line `length` frames long.
+    pub fn new(length: ctypes::c_int, stereo: bool) -> Result<Self> {
+        let subsystem = Self::subsystem();
+        let raw = pd_func_caller!((*subsystem).newDelayLine, length, stereo as ctypes::c_int)?;
+        Ok(Self {
+            inner: EffectInner::new(raw, unsafe { (*subsystem).freeDelayLine })?,
+        })
+    }
+
+    fn subsystem() -> *const crankstart_sys::playdate_sound_effect_delayline {
+        unsafe { (*effect_subsystem()).delayline }
+    }
+
+    /// Changes the length, in frames.  Taps must not be longer than the line.
+    pub fn set_length(&self, length: ctypes::c_int) -> Result<()> {
+        pd_func_caller!((*Self::subsystem()).setLength, self.inner.raw, length)
+    }
+
+    /// Sets how much of the output is fed back in, out of 1, for repeating echoes.
+    pub fn set_feedback(&self, feedback: f32) -> Result<()> {
+        pd_func_caller!((*Self::subsystem()).setFeedback, self.inner.raw, feedback)
+    }
+
+    /// Adds a tap `delay` frames into the line, no longer than the line itself.
+    pub fn add_tap(&self, delay: ctypes::c_int) -> Result<DelayLineTap> {
+        let raw_tap = pd_func_caller!((*Self::subsystem()).addTap, self.inner.raw, delay)?;
+        ensure!(!raw_tap.is_null(), "Null returned from delayline.addTap");
+        Ok(DelayLineTap {
+            raw_tap,
+            delay_line: self.clone(),
+            delay_modulator: None,
+        })
+    }
+}
+
+impl SoundEffect for DelayLine {
+    fn raw_effect(&self) -> *mut crankstart_sys::SoundEffect {
+        self.inner.raw as *mut crankstart_sys::SoundEffect
+    }
+
+    fn clone_effect(&self) -> Box<dyn SoundEffect> {
+        Box::new(self.clone())
+    }
+
+    fn set_mix_modulator(&self, modulator: Option<&dyn Modulator>) -> Result<()> {
+        self.inner.set_mix_modulator(modulator)
+    }
+}
+
+/// A sound source reading a `DelayLine` at a shorter delay.  Add it to a channel to hear it.
+#[derive(Debug)]
+pub struct DelayLineTap {
+    raw_tap: *mut crankstart_sys::DelayLineTap,
+    // The tap reads from the line, so hold on to it.
+    delay_line: DelayLine,
+    delay_modulator: Option<Box<dyn Modulator>>,
+}
+
+impl Drop for DelayLineTap {
+    fn drop(&mut self) {
+        // Use _log to leak rather than fail
+        pd_func_caller_log!((*DelayLine::subsystem()).freeTap, self.raw_tap);
+    }
+}
+
+impl DelayLineTap {
+    /// Sets the delay, in frames, no longer than the line.
+    pub fn set_delay(&self, delay: ctypes::c_int) -> Result<()> {
+        pd_func_caller!((*DelayLine::subsystem()).setTapDelay, self.raw_tap, delay)
+    }
+
+    pub fn set_delay_modulator(&mut self, modulator: Option<&dyn Modulator>) -> Result<()> {
+        let (raw_signal, modulator) = modulator_parts(modulator);
+        pd_func_caller!(
+            (*DelayLine::subsystem()).setTapDelayModulator,
+            self.raw_tap,
+            raw_signal
+        )?;
+        self.delay_modulator = modulator;
+        Ok(())
+    }
+
+    /// Swaps the left and right channels of a stereo delay line's output, for a ping-pong
+    /// echo.
+    pub fn set_channels_flipped(&self, flipped: bool) -> Result<()> {
+        pd_func_caller!(
+            (*DelayLine::subsystem()).setTapChannelsFlipped,
+            self.raw_tap,
+            flipped as ctypes::c_int
+        )
+    }
+}
+
+impl SoundSource for DelayLineTap {
+    fn raw_source(&self) -> *mut crankstart_sys::SoundSource {
+        self.raw_tap as *mut crankstart_sys::SoundSource
+    }
+}
+
+/// Boosts and clips the signal for distortion.
+#[derive(Clone, Debug)]
+pub struct Overdrive {
+    inner: Rc<EffectInner<crankstart_sys::Overdrive>>,
+}
+
+impl Overdrive {
+    pub fn new() -> Result<Self> {
+        let subsystem = Self::subsystem();
+        let raw = pd_func_caller!((*subsystem).newOverdrive)?;
+        Ok(Self {
+            inner: EffectInner::new(raw, unsafe { (*subsystem).freeOverdrive })?,
+        })
+    }
+
+    fn subsystem() -> *const crankstart_sys::playdate_sound_effect_overdrive {
+        unsafe { (*effect_subsystem()).overdrive }
+    }
+
+    /// Sets how much the signal is boosted before clipping.
+    pub fn set_gain(&self, gain: f32) -> Result<()> {
+        pd_func_caller!((*Self::subsystem()).setGain, self.inner.raw, gain)
+    }
+
+    /// Sets the level the signal is clipped at, out of 1.
+    pub fn set_limit(&self, limit: f32) -> Result<()> {
+        pd_func_caller!((*Self::subsystem()).setLimit, self.inner.raw, limit)
+    }
+
+    pub fn set_limit_modulator(&self, modulator: Option<&dyn Modulator>) -> Result<()> {
+        let (raw_signal, modulator) = modulator_parts(modulator);
+        pd_func_caller!(
+            (*Self::subsystem()).setLimitModulator,
+            self.inner.raw,
+            raw_signal
+        )?;
+        self.inner.hold("limit", modulator);
+        Ok(())
+    }
+
+    /// Adds a DC offset before clipping, which makes the distortion asymmetric.
+    pub fn set_offset(&self, offset: f32) -> Result<()> {
+        pd_func_caller!((*Self::subsystem()).setOffset, self.inner.raw, offset)
+    }
+
+    pub fn set_offset_modulator(&self, modulator: Option<&dyn Modulator>) -> Result<()> {
+        let (raw_signal, modulator) = modulator_parts(modulator);
+        pd_func_caller!(
+            (*Self::subsystem()).setOffsetModulator,
+            self.inner.raw,
+            raw_signal
+        )?;
+        self.inner.hold("offset", modulator);
+        Ok(())
+    }
+}
+
+impl SoundEffect for Overdrive {
+    fn raw_effect(&self) -> *mut crankstart_sys::SoundEffect {
+        self.inner.raw as *mut crankstart_sys::SoundEffect
+    }
+
+    fn clone_effect(&self) -> Box<dyn SoundEffect> {
+        Box::new(self.clone())
+    }
+
+    fn set_mix_modulator(&self, modulator: Option<&dyn Modulator>) -> Result<()> {
+        self.inner.set_mix_modulator(modulator)
+    }
+}
+
+/// Processes a channel's audio in Rust, 44,100 frames a second.  `process` is called from the
+/// audio thread, so it should be quick and must not call back into the Playdate API.
+///
+/// Samples are signed 8.24 fixed point, so full scale is `1 << 24`.
+pub trait AudioEffect: Send + 'static {
+    /// Processes the left and right buffers in place.  `active` is false if the channel gave
+    /// the effect silence, e.g. so a reverb can play out its tail.  Returns false if the
+    /// buffers were left silent.
+    fn process(&mut self, left: &mut [i32], right: &mut [i32], active: bool) -> bool;
+}
+
+/// An `AudioEffect` that can be added to a channel like the built-in effects.
+///
+/// ```ignore
+/// struct Gain(i32);
+///
+/// impl AudioEffect for Gain {
+///     fn process(&mut self, left: &mut [i32], right: &mut [i32], active: bool) -> bool {
+///         for sample in left.iter_mut().chain(right.iter_mut()) {
+///             *sample = sample.saturating_mul(self.0);
+///         }
+///         active
+///     }
+/// }
+///
+/// let louder = CustomEffect::new(Gain(2))?;
+/// channel.add_effect(&louder)?;
+/// ```
+///
+/// The effect owns the processor, so share any settings to change while it's playing through
+/// atomics, e.g. in an `Arc`.
+pub struct CustomEffect<E: AudioEffect> {
+    inner: Rc<EffectInner<crankstart_sys::SoundEffect>>,
+    // Declared after `inner` so the effect calling it is freed first.  The Rc keeps its
+    // address, which the effect holds as userdata, from moving.
+    processor: Rc<UnsafeCell<E>>,
+}
+
+impl<E: AudioEffect> CustomEffect<E> {
+    pub fn new(processor: E) -> Result<Self> {
+        let subsystem = effect_subsystem();
+        let processor = Rc::new(UnsafeCell::new(processor));
+        let raw = pd_func_caller!(
+            (*subsystem).newEffect,
+            Some(process::<E>),
+            processor.get() as *mut ctypes::c_void
+        )?;
+        Ok(Self {
+            inner: EffectInner::new(raw, unsafe { (*subsystem).freeEffect })?,
+            processor,
+        })
+    }
+}
+
+impl<E: AudioEffect> Clone for CustomEffect<E> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            processor: self.processor.clone(),
+        }
+    }
+}
+
+impl<E: AudioEffect> fmt::Debug for CustomEffect<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CustomEffect")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl<E: AudioEffect> SoundEffect for CustomEffect<E> {
+    fn raw_effect(&self) -> *mut crankstart_sys::SoundEffect {
+        self.inner.raw
+    }
+
+    fn clone_effect(&self) -> Box<dyn SoundEffect> {
+        Box::new(self.clone())
+    }
+
+    fn set_mix_modulator(&self, modulator: Option<&dyn Modulator>) -> Result<()> {
+        self.inner.set_mix_modulator(modulator)
+    }
+}
+
+extern "C" fn process<E: AudioEffect>(
+    effect: *mut crankstart_sys::SoundEffect,
+    left: *mut i32,
+    right: *mut i32,
+    len: ctypes::c_int,
+    active: ctypes::c_int,
+) -> ctypes::c_int {
+    if effect.is_null() || left.is_null() || right.is_null() || len <= 0 {
+        return 0;
+    }
+    let get_userdata = match unsafe { (*effect_subsystem()).getUserdata } {
+        Some(get_userdata) => get_userdata,
+        None => return 0,
+    };
+    let processor = unsafe { get_userdata(effect) } as *mut E;
+    if processor.is_null() {
+        return 0;
+    }
+    let len = len as usize;
+    let left = unsafe { slice::from_raw_parts_mut(left, len) };
+    let right = unsafe { slice::from_raw_parts_mut(right, len) };
+    unsafe { (*processor).process(left, right, active != 0) as ctypes::c_int }
+}
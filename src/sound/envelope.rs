@@ -0,0 +1,130 @@
+use super::{
+    signal::{Modulator, SignalInner},
+    Adsr, Sound,
+};
+use crate::{pd_func_caller, pd_func_caller_log};
+use crankstart_sys::ctypes;
+
+use alloc::{boxed::Box, rc::Rc};
+use anyhow::{anyhow, ensure, Error, Result};
+use core::ops::RangeInclusive;
+
+/// An ADSR envelope that follows the notes of the synth it modulates, e.g. to sweep a filter
+/// with each note.
+///
+/// ```ignore
+/// let pluck = Envelope::new(&Adsr { attack: 0.0, decay: 0.3, sustain: 0.0, release: 0.0 })?;
+/// synth.set_parameter_modulator(1, Some(&pluck))?;
+/// ```
+// Handles share the envelope, so clones can be held by everything it modulates.
+#[derive(Clone, Debug)]
+pub struct Envelope {
+    inner: Rc<SignalInner<crankstart_sys::PDSynthEnvelope>>,
+}
+
+impl Envelope {
+    pub fn new(adsr: &Adsr) -> Result<Self> {
+        let subsystem = Self::subsystem();
+        let raw = pd_func_caller!(
+            (*subsystem).newEnvelope,
+            adsr.attack,
+            adsr.decay,
+            adsr.sustain,
+            adsr.release
+        )?;
+        Ok(Self {
+            inner: SignalInner::new(raw, unsafe { (*subsystem).freeEnvelope })?,
+        })
+    }
+
+    fn subsystem() -> *const crankstart_sys::playdate_sound_envelope {
+        Sound::get().raw_envelope
+    }
+
+    pub fn set_adsr(&self, adsr: &Adsr) -> Result<()> {
+        self.set_attack(adsr.attack)?;
+        self.set_decay(adsr.decay)?;
+        self.set_sustain(adsr.sustain)?;
+        self.set_release(adsr.release)
+    }
+
+    /// Sets the attack time, in seconds.
+    pub fn set_attack(&self, attack: f32) -> Result<()> {
+        pd_func_caller!((*Self::subsystem()).setAttack, self.inner.raw, attack)
+    }
+
+    /// Sets the decay time, in seconds.
+    pub fn set_decay(&self, decay: f32) -> Result<()> {
+        pd_func_caller!((*Self::subsystem()).setDecay, self.inner.raw, decay)
+    }
+
+    /// Sets the sustain level, out of 1.
+    pub fn set_sustain(&self, sustain: f32) -> Result<()> {
+        pd_func_caller!((*Self::subsystem()).setSustain, self.inner.raw, sustain)
+    }
+
+    /// Sets the release time, in seconds.
+    pub fn set_release(&self, release: f32) -> Result<()> {
+        pd_func_caller!((*Self::subsystem()).setRelease, self.inner.raw, release)
+    }
+
+    /// Sets whether a note starting before the last is released carries on from the
+    /// sustain level rather than restarting the attack.
+    pub fn set_legato(&self, legato: bool) -> Result<()> {
+        pd_func_caller!(
+            (*Self::subsystem()).setLegato,
+            self.inner.raw,
+            legato as ctypes::c_int
+        )
+    }
+
+    /// Sets whether each note restarts the envelope from zero rather than from its current
+    /// value.
+    pub fn set_retrigger(&self, retrigger: bool) -> Result<()> {
+        pd_func_caller!(
+            (*Self::subsystem()).setRetrigger,
+            self.inner.raw,
+            retrigger as ctypes::c_int
+        )
+    }
+
+    /// Sets how curved the attack, decay and release are, from 0 (linear) to 1
+    /// (exponential).
+    pub fn set_curvature(&self, curvature: f32) -> Result<()> {
+        pd_func_caller!((*Self::subsystem()).setCurvature, self.inner.raw, curvature)
+    }
+
+    /// Sets how much note velocity scales the envelope, out of 1.
+    pub fn set_velocity_sensitivity(&self, sensitivity: f32) -> Result<()> {
+        pd_func_caller!(
+            (*Self::subsystem()).setVelocitySensitivity,
+            self.inner.raw,
+            sensitivity
+        )
+    }
+
+    /// Shortens the envelope for higher notes, by `scaling` across the MIDI notes in `notes`.
+    pub fn set_rate_scaling(&self, scaling: f32, notes: RangeInclusive<f32>) -> Result<()> {
+        pd_func_caller!(
+            (*Self::subsystem()).setRateScaling,
+            self.inner.raw,
+            scaling,
+            *notes.start(),
+            *notes.end()
+        )
+    }
+
+    pub fn get_value(&self) -> Result<f32> {
+        pd_func_caller!((*Self::subsystem()).getValue, self.inner.raw)
+    }
+}
+
+impl Modulator for Envelope {
+    fn raw_signal_value(&self) -> *mut crankstart_sys::PDSynthSignalValue {
+        self.inner.raw_signal_value()
+    }
+
+    fn clone_modulator(&self) -> Box<dyn Modulator> {
+        Box::new(self.clone())
+    }
+}
@@ -1,7 +1,9 @@
+use super::{callback::SoundCallback, source::SoundSource};
 use crate::{pd_func_caller, pd_func_caller_log};
 use crankstart_sys::ctypes;
 
 use anyhow::{anyhow, ensure, Error, Result};
+use core::ptr;
 use cstr_core::CString;
 
 /// Note: Make sure you hold on to a FilePlayer until the file has played as much as you want,
@@ -10,6 +12,11 @@ use cstr_core::CString;
 pub struct FilePlayer {
     raw_subsystem: *const crankstart_sys::playdate_sound_fileplayer,
     raw_player: *mut crankstart_sys::FilePlayer,
+
+    // The callbacks given to the player, held so they're not freed while it can call them.
+    finish_callback: Option<SoundCallback>,
+    loop_callback: Option<SoundCallback>,
+    fade_callback: Option<SoundCallback>,
 }
 
 impl Drop for FilePlayer {
@@ -19,8 +26,8 @@ impl Drop for FilePlayer {
     }
 }
 
-// Not implemented: newPlayer (use Sound::get_file_player), setFinishCallback and fadeVolume
-// (waiting on crankstart callback strategy), and setLoopRange (does not seem to do anything).
+// Not implemented: newPlayer (use Sound::get_file_player), and setLoopRange (does not seem to
+// do anything).
 impl FilePlayer {
     pub(crate) fn new(
         raw_subsystem: *const crankstart_sys::playdate_sound_fileplayer,
@@ -37,6 +44,9 @@ impl FilePlayer {
         Ok(Self {
             raw_subsystem,
             raw_player,
+            finish_callback: None,
+            loop_callback: None,
+            fade_callback: None,
         })
     }
 
@@ -177,4 +187,82 @@ impl FilePlayer {
     pub fn get_length(&self) -> Result<f32> {
         pd_func_caller!((*self.raw_subsystem).getLength, self.raw_player)
     }
+
+    /// Calls `callback` each time the player finishes playing, or is stopped.  Like all sound
+    /// callbacks, it's called at the start of the next update rather than straight away.
+    pub fn set_finish_callback(&mut self, callback: impl FnMut() + 'static) -> Result<()> {
+        let callback = SoundCallback::new(callback);
+        pd_func_caller!(
+            (*self.raw_subsystem).setFinishCallback,
+            self.raw_player,
+            Some(SoundCallback::proc),
+            callback.userdata()
+        )?;
+        self.finish_callback = Some(callback);
+        Ok(())
+    }
+
+    pub fn clear_finish_callback(&mut self) -> Result<()> {
+        pd_func_caller!(
+            (*self.raw_subsystem).setFinishCallback,
+            self.raw_player,
+            None,
+            ptr::null_mut()
+        )?;
+        self.finish_callback = None;
+        Ok(())
+    }
+
+    /// Calls `callback` each time playback loops back to the start.
+    pub fn set_loop_callback(&mut self, callback: impl FnMut() + 'static) -> Result<()> {
+        let callback = SoundCallback::new(callback);
+        pd_func_caller!(
+            (*self.raw_subsystem).setLoopCallback,
+            self.raw_player,
+            Some(SoundCallback::proc),
+            callback.userdata()
+        )?;
+        self.loop_callback = Some(callback);
+        Ok(())
+    }
+
+    pub fn clear_loop_callback(&mut self) -> Result<()> {
+        pd_func_caller!(
+            (*self.raw_subsystem).setLoopCallback,
+            self.raw_player,
+            None,
+            ptr::null_mut()
+        )?;
+        self.loop_callback = None;
+        Ok(())
+    }
+
+    /// Fades the left and right volumes, out of 1, to the given levels over `length` frames
+    /// (44,100 a second), then calls `on_done`.
+    pub fn fade_volume(
+        &mut self,
+        left: f32,
+        right: f32,
+        length: i32,
+        on_done: impl FnOnce() + 'static,
+    ) -> Result<()> {
+        let callback = SoundCallback::once(on_done);
+        pd_func_caller!(
+            (*self.raw_subsystem).fadeVolume,
+            self.raw_player,
+            left,
+            right,
+            length,
+            Some(SoundCallback::proc),
+            callback.userdata()
+        )?;
+        self.fade_callback = Some(callback);
+        Ok(())
+    }
+}
+
+impl SoundSource for FilePlayer {
+    fn raw_source(&self) -> *mut crankstart_sys::SoundSource {
+        self.raw_player as *mut crankstart_sys::SoundSource
+    }
 }
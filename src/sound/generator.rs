@@ -0,0 +1,141 @@
+//! Sound sources that generate their samples in Rust.
+//!
+//! ```ignore
+//! struct Tone {
+//!     phase: f32,
+//! }
+//!
+//! impl AudioGenerator for Tone {
+//!     fn render(&mut self, left: &mut [i16], _right: &mut [i16]) -> bool {
+//!         for sample in left.iter_mut() {
+//!             *sample = (libm::sinf(self.phase) * 8000.0) as i16;
+//!             self.phase = (self.phase + 440.0 * core::f32::consts::TAU / 44100.0)
+//!                 % core::f32::consts::TAU;
+//!         }
+//!         true
+//!     }
+//!
+//!     fn is_stereo(&self) -> bool {
+//!         false
+//!     }
+//! }
+//!
+//! let tone = channel.add_generator(Tone { phase: 0.0 })?;
+//! ```
+
+use super::{source::SoundSource, Channel, Sound};
+use crate::{pd_func_caller, pd_func_caller_log};
+use crankstart_sys::ctypes;
+
+use alloc::boxed::Box;
+use anyhow::{anyhow, ensure, Error, Result};
+use core::slice;
+
+/// Generates audio, 44,100 frames a second.  `render` is called from the audio thread, so it
+/// should be quick and must not call back into the Playdate API.
+pub trait AudioGenerator: Send + 'static {
+    /// Fills `left` and `right` with the next frames.  Mono generators fill `left` and get an
+    /// empty `right`.  Returns false if the buffers were left silent, which lets the sound
+    /// engine skip mixing them.
+    fn render(&mut self, left: &mut [i16], right: &mut [i16]) -> bool;
+
+    fn is_stereo(&self) -> bool {
+        true
+    }
+}
+
+/// A generator playing through a channel.  The generator is removed from the channel when
+/// this is dropped, so it's never called after it's freed.
+#[derive(Debug)]
+pub struct GeneratorSource<G: AudioGenerator> {
+    raw_source: *mut crankstart_sys::SoundSource,
+    // Boxed so its address, which the sound engine calls it with, doesn't move.
+    generator: Option<Box<G>>,
+}
+
+impl<G: AudioGenerator> Drop for GeneratorSource<G> {
+    fn drop(&mut self) {
+        if self.generator.is_some() {
+            // Use _log to leak rather than fail
+            pd_func_caller_log!((*Sound::get().raw_sound).removeSource, self.raw_source);
+        }
+    }
+}
+
+impl<G: AudioGenerator> GeneratorSource<G> {
+    /// Stops playing and returns the generator.
+    pub fn into_generator(mut self) -> Result<Box<G>> {
+        pd_func_caller!((*Sound::get().raw_sound).removeSource, self.raw_source)?;
+        self.generator
+            .take()
+            .ok_or_else(|| anyhow!("Generator already removed"))
+    }
+}
+
+impl<G: AudioGenerator> SoundSource for GeneratorSource<G> {
+    fn raw_source(&self) -> *mut crankstart_sys::SoundSource {
+        self.raw_source
+    }
+}
+
+impl Sound {
+    /// Plays `generator` through the default channel.
+    pub fn add_generator<G: AudioGenerator>(&self, generator: G) -> Result<GeneratorSource<G>> {
+        let stereo = generator.is_stereo();
+        let mut generator = Box::new(generator);
+        let raw_source = pd_func_caller!(
+            (*self.raw_sound).addSource,
+            Some(render::<G>),
+            &mut *generator as *mut G as *mut ctypes::c_void,
+            stereo as ctypes::c_int
+        )?;
+        ensure!(!raw_source.is_null(), "Null returned from sound.addSource");
+        Ok(GeneratorSource {
+            raw_source,
+            generator: Some(generator),
+        })
+    }
+}
+
+impl Channel {
+    /// Plays `generator` through this channel.
+    pub fn add_generator<G: AudioGenerator>(&self, generator: G) -> Result<GeneratorSource<G>> {
+        let stereo = generator.is_stereo();
+        let mut generator = Box::new(generator);
+        let raw_source = pd_func_caller!(
+            (*Sound::get().raw_channel).addCallbackSource,
+            self.raw_channel(),
+            Some(render::<G>),
+            &mut *generator as *mut G as *mut ctypes::c_void,
+            stereo as ctypes::c_int
+        )?;
+        ensure!(
+            !raw_source.is_null(),
+            "Null returned from channel.addCallbackSource"
+        );
+        Ok(GeneratorSource {
+            raw_source,
+            generator: Some(generator),
+        })
+    }
+}
+
+extern "C" fn render<G: AudioGenerator>(
+    context: *mut ctypes::c_void,
+    left: *mut i16,
+    right: *mut i16,
+    len: ctypes::c_int,
+) -> ctypes::c_int {
+    if context.is_null() || left.is_null() || len <= 0 {
+        return 0;
+    }
+    let generator = unsafe { &mut *(context as *mut G) };
+    let len = len as usize;
+    let left = unsafe { slice::from_raw_parts_mut(left, len) };
+    let right = if generator.is_stereo() && !right.is_null() {
+        unsafe { slice::from_raw_parts_mut(right, len) }
+    } else {
+        &mut []
+    };
+    generator.render(left, right) as ctypes::c_int
+}
@@ -0,0 +1,215 @@
+use super::{source::SoundSource, Sound, Synth};
+use crate::{pd_func_caller, pd_func_caller_log};
+use crankstart_sys::ctypes;
+
+use alloc::{rc::Rc, vec::Vec};
+use anyhow::{anyhow, ensure, Error, Result};
+use core::{cell::RefCell, ops::RangeInclusive};
+
+/// A set of `Synth` voices played together, e.g. for a sequence track.  Each note goes to a
+/// free voice whose note range covers it, so add as many voices as notes that play at once.
+///
+/// ```ignore
+/// let instrument = Instrument::new()?;
+/// let synth = Synth::new()?;
+/// synth.set_waveform(SoundWaveform::kWaveformTriangle)?;
+/// for _ in 0..track.get_polyphony()? {
+///     instrument.add_voice(synth.copy()?, None, 0.0)?;
+/// }
+/// track.set_instrument(&instrument)?;
+/// ```
+// Really a wrapper around an Rc clone of the internal structure, so tracks can hold on to it.
+#[derive(Clone, Debug)]
+pub struct Instrument {
+    inner: Rc<RefCell<InstrumentInner>>,
+}
+
+#[derive(Debug)]
+struct InstrumentInner {
+    raw_subsystem: *const crankstart_sys::playdate_sound_instrument,
+    raw_instrument: *mut crankstart_sys::PDSynthInstrument,
+    // The instrument plays its voices but doesn't free them, so they're freed after it.
+    voices: Vec<Synth>,
+}
+
+impl Drop for InstrumentInner {
+    fn drop(&mut self) {
+        // Use _log to leak rather than fail
+        pd_func_caller_log!((*self.raw_subsystem).freeInstrument, self.raw_instrument);
+    }
+}
+
+impl Instrument {
+    pub fn new() -> Result<Self> {
+        let raw_subsystem = Sound::get().raw_instrument;
+        let raw_instrument = pd_func_caller!((*raw_subsystem).newInstrument)?;
+        ensure!(
+            !raw_instrument.is_null(),
+            "Null returned from instrument.newInstrument"
+        );
+        Ok(Self {
+            inner: Rc::new(RefCell::new(InstrumentInner {
+                raw_subsystem,
+                raw_instrument,
+                voices: Vec::new(),
+            })),
+        })
+    }
+
+    pub(crate) fn raw_instrument(&self) -> *mut crankstart_sys::PDSynthInstrument {
+        self.inner.borrow().raw_instrument
+    }
+
+    fn raw_subsystem(&self) -> *const crankstart_sys::playdate_sound_instrument {
+        self.inner.borrow().raw_subsystem
+    }
+
+    /// Adds a voice that plays the MIDI notes in `notes`, or every note if None, shifted by
+    /// `transpose` half steps.  The synth can't already be in an instrument or channel.
+    pub fn add_voice(
+        &self,
+        synth: Synth,
+        notes: Option<RangeInclusive<f32>>,
+        transpose: f32,
+    ) -> Result<()> {
+        let notes = notes.unwrap_or(0.0..=127.0);
+        let result = pd_func_caller!(
+            (*self.raw_subsystem()).addVoice,
+            self.raw_instrument(),
+            synth.raw_synth(),
+            *notes.start(),
+            *notes.end(),
+            transpose
+        )?;
+        ensure!(
+            result == 1,
+            "instrument.addVoice failed; the synth is already in an instrument or channel"
+        );
+        self.inner.borrow_mut().voices.push(synth);
+        Ok(())
+    }
+
+    pub fn voice_count(&self) -> usize {
+        self.inner.borrow().voices.len()
+    }
+
+    /// Plays a note of `frequency` Hz on a free voice, like `Synth::play_note`.
+    pub fn play_note(
+        &self,
+        frequency: f32,
+        velocity: f32,
+        length: Option<f32>,
+        when: u32,
+    ) -> Result<()> {
+        pd_func_caller!(
+            (*self.raw_subsystem()).playNote,
+            self.raw_instrument(),
+            frequency,
+            velocity,
+            length.unwrap_or(-1.0),
+            when
+        )?;
+        Ok(())
+    }
+
+    /// Plays a MIDI note number on a free voice, like `Synth::play_midi_note`.
+    pub fn play_midi_note(
+        &self,
+        note: f32,
+        velocity: f32,
+        length: Option<f32>,
+        when: u32,
+    ) -> Result<()> {
+        pd_func_caller!(
+            (*self.raw_subsystem()).playMIDINote,
+            self.raw_instrument(),
+            note,
+            velocity,
+            length.unwrap_or(-1.0),
+            when
+        )?;
+        Ok(())
+    }
+
+    /// Releases the voice playing MIDI note `note` at sound engine time `when`, or 0 for now.
+    pub fn note_off(&self, note: f32, when: u32) -> Result<()> {
+        pd_func_caller!(
+            (*self.raw_subsystem()).noteOff,
+            self.raw_instrument(),
+            note,
+            when
+        )
+    }
+
+    pub fn all_notes_off(&self, when: u32) -> Result<()> {
+        pd_func_caller!(
+            (*self.raw_subsystem()).allNotesOff,
+            self.raw_instrument(),
+            when
+        )
+    }
+
+    /// Bends the pitch of every voice, from -1 to 1 times the pitch bend range.
+    pub fn set_pitch_bend(&self, bend: f32) -> Result<()> {
+        pd_func_caller!(
+            (*self.raw_subsystem()).setPitchBend,
+            self.raw_instrument(),
+            bend
+        )
+    }
+
+    /// Sets how far a full pitch bend goes, in half steps.
+    pub fn set_pitch_bend_range(&self, half_steps: f32) -> Result<()> {
+        pd_func_caller!(
+            (*self.raw_subsystem()).setPitchBendRange,
+            self.raw_instrument(),
+            half_steps
+        )
+    }
+
+    /// Shifts the pitch of every voice by `half_steps`, on top of each voice's own transpose.
+    pub fn set_transpose(&self, half_steps: f32) -> Result<()> {
+        pd_func_caller!(
+            (*self.raw_subsystem()).setTranspose,
+            self.raw_instrument(),
+            half_steps
+        )
+    }
+
+    /// Returns the number of voices currently playing.
+    pub fn active_voice_count(&self) -> Result<ctypes::c_int> {
+        pd_func_caller!(
+            (*self.raw_subsystem()).activeVoiceCount,
+            self.raw_instrument()
+        )
+    }
+
+    /// Gets the current volume of the left and right audio channels, out of 1.
+    pub fn get_volume(&self) -> Result<(f32, f32)> {
+        let mut left = 0.0;
+        let mut right = 0.0;
+        pd_func_caller!(
+            (*self.raw_subsystem()).getVolume,
+            self.raw_instrument(),
+            &mut left,
+            &mut right,
+        )?;
+        Ok((left, right))
+    }
+
+    /// Sets the volume of the left and right audio channels, out of 1.
+    pub fn set_volume(&self, left: f32, right: f32) -> Result<()> {
+        pd_func_caller!(
+            (*self.raw_subsystem()).setVolume,
+            self.raw_instrument(),
+            left,
+            right
+        )
+    }
+}
+
+impl SoundSource for Instrument {
+    fn raw_source(&self) -> *mut crankstart_sys::SoundSource {
+        self.raw_instrument() as *mut crankstart_sys::SoundSource
+    }
+}
@@ -0,0 +1,197 @@
+use super::{
+    signal::{Modulator, SignalInner},
+    Sound,
+};
+use crate::{pd_func_caller, pd_func_caller_log};
+use crankstart_sys::ctypes;
+
+use alloc::{boxed::Box, rc::Rc, vec::Vec};
+use anyhow::{anyhow, ensure, Error, Result};
+use core::{cell::RefCell, fmt};
+
+pub use crankstart_sys::LFOType;
+
+type LfoFunction = Box<dyn FnMut() -> f32 + Send>;
+
+/// A low frequency oscillator, for vibrato, tremolo, filter sweeps and the like.
+///
+/// ```ignore
+/// let vibrato = Lfo::new(LFOType::kLFOTypeSine)?;
+/// vibrato.set_rate(5.0)?;
+/// vibrato.set_depth(0.02)?;
+/// synth.set_frequency_modulator(Some(&vibrato))?;
+/// ```
+///
+/// Or with values computed in Rust:
+///
+/// ```ignore
+/// let mut value = 0.0;
+/// let steps = Lfo::new(LFOType::kLFOTypeFunction)?;
+/// steps.set_function(
+///     move || {
+///         value = if value >= 1.0 { 0.0 } else { value + 0.25 };
+///         value
+///     },
+///     false,
+/// )?;
+/// ```
+// Handles share the LFO, so clones can be held by everything it modulates.
+#[derive(Clone)]
+pub struct Lfo {
+    inner: Rc<SignalInner<crankstart_sys::PDSynthLFO>>,
+    // Declared after `inner` so the LFO using them is freed first.
+    held: Rc<RefCell<LfoHeld>>,
+}
+
+/// The function and arpeggio steps in use, held so they're not freed while the LFO uses them.
+#[derive(Default)]
+struct LfoHeld {
+    // Double boxed so the LFO's userdata is a thin pointer.
+    function: Option<Box<LfoFunction>>,
+    arpeggiation: Vec<f32>,
+}
+
+impl fmt::Debug for Lfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let held = self.held.borrow();
+        f.debug_struct("Lfo")
+            .field("inner", &self.inner)
+            .field("function", &held.function.is_some())
+            .field("arpeggiation", &held.arpeggiation)
+            .finish()
+    }
+}
+
+impl Lfo {
+    pub fn new(lfo_type: LFOType) -> Result<Self> {
+        let subsystem = Self::subsystem();
+        let raw = pd_func_caller!((*subsystem).newLFO, lfo_type)?;
+        Ok(Self {
+            inner: SignalInner::new(raw, unsafe { (*subsystem).freeLFO })?,
+            held: Rc::new(RefCell::new(LfoHeld::default())),
+        })
+    }
+
+    fn subsystem() -> *const crankstart_sys::playdate_sound_lfo {
+        Sound::get().raw_lfo
+    }
+
+    pub fn set_type(&self, lfo_type: LFOType) -> Result<()> {
+        pd_func_caller!((*Self::subsystem()).setType, self.inner.raw, lfo_type)
+    }
+
+    /// Sets the rate, in cycles per second.
+    pub fn set_rate(&self, rate: f32) -> Result<()> {
+        pd_func_caller!((*Self::subsystem()).setRate, self.inner.raw, rate)
+    }
+
+    /// Sets the current phase, out of 1.
+    pub fn set_phase(&self, phase: f32) -> Result<()> {
+        pd_func_caller!((*Self::subsystem()).setPhase, self.inner.raw, phase)
+    }
+
+    /// Sets the phase, out of 1, that the LFO restarts from when retriggered.
+    pub fn set_start_phase(&self, phase: f32) -> Result<()> {
+        pd_func_caller!((*Self::subsystem()).setStartPhase, self.inner.raw, phase)
+    }
+
+    /// Sets the value the LFO oscillates around.
+    pub fn set_center(&self, center: f32) -> Result<()> {
+        pd_func_caller!((*Self::subsystem()).setCenter, self.inner.raw, center)
+    }
+
+    /// Sets how far the LFO swings either side of its center.
+    pub fn set_depth(&self, depth: f32) -> Result<()> {
+        pd_func_caller!((*Self::subsystem()).setDepth, self.inner.raw, depth)
+    }
+
+    /// Makes this an arpeggiator, stepping through `steps`, in half steps from the note
+    /// played, at the LFO's rate.
+    pub fn set_arpeggiation(&self, steps: &[f32]) -> Result<()> {
+        let mut held = self.held.borrow_mut();
+        let mut steps = steps.to_vec();
+        pd_func_caller!(
+            (*Self::subsystem()).setArpeggiation,
+            self.inner.raw,
+            steps.len() as ctypes::c_int,
+            steps.as_mut_ptr()
+        )?;
+        held.arpeggiation = steps;
+        Ok(())
+    }
+
+    /// Makes the LFO call `function` for each new value, at its rate.  If `interpolate` is
+    /// true, the output ramps between values rather than stepping.  `function` is called from
+    /// the audio thread, so it should be quick and must not call back into the Playdate API.
+    pub fn set_function<F>(&self, function: F, interpolate: bool) -> Result<()>
+    where
+        F: FnMut() -> f32 + Send + 'static,
+    {
+        let mut held = self.held.borrow_mut();
+        let mut function: Box<LfoFunction> = Box::new(Box::new(function));
+        pd_func_caller!(
+            (*Self::subsystem()).setFunction,
+            self.inner.raw,
+            Some(lfo_function),
+            &mut *function as *mut LfoFunction as *mut ctypes::c_void,
+            interpolate as ctypes::c_int
+        )?;
+        held.function = Some(function);
+        Ok(())
+    }
+
+    /// Holds the LFO at its center for `holdoff` seconds after a note starts, then ramps it
+    /// to full depth over `ramp_time` seconds.
+    pub fn set_delay(&self, holdoff: f32, ramp_time: f32) -> Result<()> {
+        pd_func_caller!(
+            (*Self::subsystem()).setDelay,
+            self.inner.raw,
+            holdoff,
+            ramp_time
+        )
+    }
+
+    /// Sets whether the LFO restarts from its start phase on each note.
+    pub fn set_retrigger(&self, retrigger: bool) -> Result<()> {
+        pd_func_caller!(
+            (*Self::subsystem()).setRetrigger,
+            self.inner.raw,
+            retrigger as ctypes::c_int
+        )
+    }
+
+    /// Sets whether the LFO keeps running while no note is playing, shared by every voice it
+    /// modulates.
+    pub fn set_global(&self, global: bool) -> Result<()> {
+        pd_func_caller!(
+            (*Self::subsystem()).setGlobal,
+            self.inner.raw,
+            global as ctypes::c_int
+        )
+    }
+
+    pub fn get_value(&self) -> Result<f32> {
+        pd_func_caller!((*Self::subsystem()).getValue, self.inner.raw)
+    }
+}
+
+impl Modulator for Lfo {
+    fn raw_signal_value(&self) -> *mut crankstart_sys::PDSynthSignalValue {
+        self.inner.raw_signal_value()
+    }
+
+    fn clone_modulator(&self) -> Box<dyn Modulator> {
+        Box::new(self.clone())
+    }
+}
+
+extern "C" fn lfo_function(
+    _lfo: *mut crankstart_sys::PDSynthLFO,
+    userdata: *mut ctypes::c_void,
+) -> f32 {
+    if userdata.is_null() {
+        return 0.0;
+    }
+    let function = unsafe { &mut *(userdata as *mut LfoFunction) };
+    function()
+}
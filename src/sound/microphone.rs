@@ -0,0 +1,295 @@
+//! Microphone input, and the headphone state that decides which mic is used.
+//!
+//! To record a few seconds into a sample:
+//!
+//! ```ignore
+//! let mic = Microphone::start(MicSource::kMicInputAutodetect, Recorder::new(44100 * 3))?;
+//! // Later, once `mic.is_listening()` is false or the player lets go of the button:
+//! let sample = mic.stop()?.into_sample()?;
+//! ```
+
+use super::{callback::SoundCallback, AudioSample, Sound};
+use crate::{pd_func_caller, pd_func_caller_log};
+use crankstart_sys::ctypes;
+
+use alloc::{boxed::Box, vec::Vec};
+use anyhow::{anyhow, ensure, Error, Result};
+use core::{
+    ptr, slice,
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicU32, AtomicU8, Ordering},
+};
+
+pub use crankstart_sys::MicSource;
+
+/// Receives microphone input, 44,100 mono frames a second.  `input` is called from the audio
+/// thread, so it should be quick and must not call back into the Playdate API.
+pub trait MicListener: Send + 'static {
+    /// Handles the next frames of input.  Returns false to stop listening.
+    fn input(&mut self, frames: &[i16]) -> bool;
+}
+
+impl<F: FnMut(&[i16]) -> bool + Send + 'static> MicListener for F {
+    fn input(&mut self, frames: &[i16]) -> bool {
+        self(frames)
+    }
+}
+
+/// A `MicListener` that records up to a given number of frames, then stops listening.
+#[derive(Debug)]
+pub struct Recorder {
+    // Allocated up front, so recording doesn't allocate on the audio thread.
+    frames: Vec<i16>,
+}
+
+impl Recorder {
+    pub fn new(max_frames: usize) -> Self {
+        Self {
+            frames: Vec::with_capacity(max_frames),
+        }
+    }
+
+    pub fn frames(&self) -> &[i16] {
+        &self.frames
+    }
+
+    /// Returns the recording as a mono sample.
+    pub fn into_sample(self) -> Result<AudioSample> {
+        AudioSample::from_pcm16(&self.frames, false, 44100)
+    }
+}
+
+impl MicListener for Recorder {
+    fn input(&mut self, frames: &[i16]) -> bool {
+        let room = self.frames.capacity() - self.frames.len();
+        self.frames
+            .extend_from_slice(&frames[..frames.len().min(room)]);
+        self.frames.len() < self.frames.capacity()
+    }
+}
+
+// The listener the mic is calling, so a stopped `Microphone` doesn't stop a newer one.
+static CURRENT_MIC: AtomicPtr<ctypes::c_void> = AtomicPtr::new(ptr::null_mut());
+
+struct MicState<L> {
+    listener: L,
+    listening: AtomicBool,
+    // The peak level of the last input, out of 1, as f32 bits.
+    level: AtomicU32,
+}
+
+/// Microphone input going to a `MicListener`.  Only one microphone listens at a time; starting
+/// another replaces this one.  Input stops when this is dropped.
+pub struct Microphone<L: MicListener> {
+    // Boxed so its address, which the sound engine calls it with, doesn't move.
+    state: Option<Box<MicState<L>>>,
+}
+
+impl<L: MicListener> core::fmt::Debug for Microphone<L> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Microphone")
+            .field("listening", &self.is_listening())
+            .field("level", &self.get_level())
+            .finish()
+    }
+}
+
+impl<L: MicListener> Drop for Microphone<L> {
+    fn drop(&mut self) {
+        if self.is_current() {
+            // Use _log to leak rather than fail
+            pd_func_caller_log!(
+                (*Sound::get().raw_sound).setMicCallback,
+                None,
+                ptr::null_mut(),
+                MicSource::kMicInputAutodetect
+            );
+            CURRENT_MIC.store(ptr::null_mut(), Ordering::Release);
+        }
+    }
+}
+
+impl<L: MicListener> Microphone<L> {
+    /// Starts sending input from `source` to `listener`.  `kMicInputAutodetect` uses the
+    /// headset mic if one is plugged in, and the device mic otherwise.
+    pub fn start(source: MicSource, listener: L) -> Result<Self> {
+        let mut state = Box::new(MicState {
+            listener,
+            listening: AtomicBool::new(true),
+            level: AtomicU32::new(0),
+        });
+        let context = &mut *state as *mut MicState<L> as *mut ctypes::c_void;
+        pd_func_caller!(
+            (*Sound::get().raw_sound).setMicCallback,
+            Some(record::<L>),
+            context,
+            source
+        )?;
+        CURRENT_MIC.store(context, Ordering::Release);
+        Ok(Self { state: Some(state) })
+    }
+
+    fn is_current(&self) -> bool {
+        match &self.state {
+            Some(state) => {
+                CURRENT_MIC.load(Ordering::Acquire)
+                    == &**state as *const MicState<L> as *mut ctypes::c_void
+            }
+            None => false,
+        }
+    }
+
+    /// Returns false once the listener has stopped listening, or another microphone has
+    /// replaced this one.
+    pub fn is_listening(&self) -> bool {
+        match &self.state {
+            Some(state) => self.is_current() && state.listening.load(Ordering::Acquire),
+            None => false,
+        }
+    }
+
+    /// Returns the peak level of the most recent input, out of 1.
+    pub fn get_level(&self) -> f32 {
+        match &self.state {
+            Some(state) => f32::from_bits(state.level.load(Ordering::Relaxed)),
+            None => 0.0,
+        }
+    }
+
+    /// Stops listening and returns the listener.
+    pub fn stop(mut self) -> Result<L> {
+        if self.is_current() {
+            pd_func_caller!(
+                (*Sound::get().raw_sound).setMicCallback,
+                None,
+                ptr::null_mut(),
+                MicSource::kMicInputAutodetect
+            )?;
+            CURRENT_MIC.store(ptr::null_mut(), Ordering::Release);
+        }
+        let state = self
+            .state
+            .take()
+            .ok_or_else(|| anyhow!("Microphone already stopped"))?;
+        Ok(state.listener)
+    }
+}
+
+extern "C" fn record<L: MicListener>(
+    context: *mut ctypes::c_void,
+    buffer: *mut i16,
+    length: ctypes::c_int,
+) -> ctypes::c_int {
+    if context.is_null() {
+        return 0;
+    }
+    let state = unsafe { &mut *(context as *mut MicState<L>) };
+    if buffer.is_null() || length <= 0 {
+        return 1;
+    }
+    let frames = unsafe { slice::from_raw_parts(buffer, length as usize) };
+    let peak = frames
+        .iter()
+        .map(|&frame| (frame as i32).abs())
+        .max()
+        .unwrap_or(0);
+    state
+        .level
+        .store((peak as f32 / 32768.0).to_bits(), Ordering::Relaxed);
+    let listening = state.listener.input(frames);
+    state.listening.store(listening, Ordering::Release);
+    listening as ctypes::c_int
+}
+
+/// Whether headphones, and a headset mic, are plugged in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HeadphoneState {
+    pub headphones: bool,
+    pub headset_mic: bool,
+}
+
+impl HeadphoneState {
+    fn to_bits(self) -> u8 {
+        self.headphones as u8 | (self.headset_mic as u8) << 1
+    }
+
+    fn from_bits(bits: u8) -> Self {
+        Self {
+            headphones: bits & 1 != 0,
+            headset_mic: bits & 2 != 0,
+        }
+    }
+}
+
+// The headphone change callback gets no userdata, so its state lives here.  The callback
+// marks HEADPHONE_CALLBACK due, and the closure reads the state when it's run.
+static HEADPHONE_STATE: AtomicU8 = AtomicU8::new(0);
+static HEADPHONE_CALLBACK: AtomicPtr<ctypes::c_void> = AtomicPtr::new(ptr::null_mut());
+// Holds the callback that HEADPHONE_CALLBACK points to.  Only touched on the main thread.
+static mut HEADPHONE_SOUND_CALLBACK: Option<SoundCallback> = None;
+
+impl Sound {
+    pub fn get_headphone_state(&self) -> Result<HeadphoneState> {
+        let callback = if HEADPHONE_CALLBACK.load(Ordering::Acquire).is_null() {
+            None
+        } else {
+            Some(headphone_changed as unsafe extern "C" fn(ctypes::c_int, ctypes::c_int))
+        };
+        let state = self.raw_headphone_state(callback)?;
+        HEADPHONE_STATE.store(state.to_bits(), Ordering::Release);
+        Ok(state)
+    }
+
+    fn raw_headphone_state(
+        &self,
+        callback: Option<unsafe extern "C" fn(ctypes::c_int, ctypes::c_int)>,
+    ) -> Result<HeadphoneState> {
+        let mut headphones = 0;
+        let mut headset_mic = 0;
+        pd_func_caller!(
+            (*self.raw_sound).getHeadphoneState,
+            &mut headphones,
+            &mut headset_mic,
+            callback
+        )?;
+        Ok(HeadphoneState {
+            headphones: headphones != 0,
+            headset_mic: headset_mic != 0,
+        })
+    }
+
+    /// Calls `callback` whenever headphones are plugged in or out, e.g. to switch to a mix
+    /// suited to the speaker.  Like all sound callbacks, it's called at the start of the next
+    /// update rather than straight away.  Replaces any previous headphone callback.
+    pub fn set_headphone_callback(
+        &self,
+        mut callback: impl FnMut(HeadphoneState) + 'static,
+    ) -> Result<HeadphoneState> {
+        let sound_callback = SoundCallback::new(move || {
+            callback(HeadphoneState::from_bits(
+                HEADPHONE_STATE.load(Ordering::Acquire),
+            ))
+        });
+        HEADPHONE_CALLBACK.store(sound_callback.userdata(), Ordering::Release);
+        let state = self.raw_headphone_state(Some(headphone_changed))?;
+        HEADPHONE_STATE.store(state.to_bits(), Ordering::Release);
+        unsafe { HEADPHONE_SOUND_CALLBACK = Some(sound_callback) };
+        Ok(state)
+    }
+
+    pub fn clear_headphone_callback(&self) -> Result<()> {
+        self.raw_headphone_state(None)?;
+        HEADPHONE_CALLBACK.store(ptr::null_mut(), Ordering::Release);
+        unsafe { HEADPHONE_SOUND_CALLBACK = None };
+        Ok(())
+    }
+}
+
+unsafe extern "C" fn headphone_changed(headphones: ctypes::c_int, headset_mic: ctypes::c_int) {
+    let state = HeadphoneState {
+        headphones: headphones != 0,
+        headset_mic: headset_mic != 0,
+    };
+    HEADPHONE_STATE.store(state.to_bits(), Ordering::Release);
+    let userdata = HEADPHONE_CALLBACK.load(Ordering::Acquire);
+    SoundCallback::proc::<ctypes::c_void>(ptr::null_mut(), userdata);
+}
@@ -1,8 +1,13 @@
+use super::{callback::SoundCallback, source::SoundSource, Sound};
 use crate::{log_to_console, pd_func_caller, pd_func_caller_log};
 use crankstart_sys::ctypes;
 
-use alloc::rc::Rc;
+use alloc::{rc::Rc, vec::Vec};
 use anyhow::{anyhow, ensure, Error, Result};
+use core::{ptr, slice};
+use cstr_core::CString;
+
+pub use crankstart_sys::SoundFormat;
 
 /// Note: Make sure you hold on to a SamplePlayer until the sample has played as much as you want,
 /// because dropping it will stop playback.
@@ -14,6 +19,10 @@ pub struct SamplePlayer {
     // We store an Rc clone of the audio sample so that it's not freed before the player is
     // finished using it, or until another sample is set.
     sample: Option<AudioSample>,
+
+    // The callbacks given to the player, held so they're not freed while it can call them.
+    finish_callback: Option<SoundCallback>,
+    loop_callback: Option<SoundCallback>,
 }
 
 impl Drop for SamplePlayer {
@@ -23,8 +32,7 @@ impl Drop for SamplePlayer {
     }
 }
 
-// Not implemented: newPlayer (use Sound::get_sample_player), and setFinishCallback and setLoopCallback
-// (waiting on crankstart callback strategy).
+// Not implemented: newPlayer (use Sound::get_sample_player).
 impl SamplePlayer {
     pub(crate) fn new(
         raw_subsystem: *const crankstart_sys::playdate_sound_sampleplayer,
@@ -42,6 +50,8 @@ impl SamplePlayer {
             raw_subsystem,
             raw_player,
             sample: None,
+            finish_callback: None,
+            loop_callback: None,
         })
     }
 
@@ -164,9 +174,75 @@ impl SamplePlayer {
     pub fn get_length(&self) -> Result<f32> {
         pd_func_caller!((*self.raw_subsystem).getLength, self.raw_player)
     }
+
+    /// Calls `callback` each time the player finishes playing, or is stopped.  Like all sound
+    /// callbacks, it's called at the start of the next update rather than straight away.
+    pub fn set_finish_callback(&mut self, callback: impl FnMut() + 'static) -> Result<()> {
+        let callback = SoundCallback::new(callback);
+        pd_func_caller!(
+            (*self.raw_subsystem).setFinishCallback,
+            self.raw_player,
+            Some(SoundCallback::proc),
+            callback.userdata()
+        )?;
+        self.finish_callback = Some(callback);
+        Ok(())
+    }
+
+    pub fn clear_finish_callback(&mut self) -> Result<()> {
+        pd_func_caller!(
+            (*self.raw_subsystem).setFinishCallback,
+            self.raw_player,
+            None,
+            ptr::null_mut()
+        )?;
+        self.finish_callback = None;
+        Ok(())
+    }
+
+    /// Calls `callback` each time playback loops, including each change of direction when
+    /// playing back and forth.
+    pub fn set_loop_callback(&mut self, callback: impl FnMut() + 'static) -> Result<()> {
+        let callback = SoundCallback::new(callback);
+        pd_func_caller!(
+            (*self.raw_subsystem).setLoopCallback,
+            self.raw_player,
+            Some(SoundCallback::proc),
+            callback.userdata()
+        )?;
+        self.loop_callback = Some(callback);
+        Ok(())
+    }
+
+    pub fn clear_loop_callback(&mut self) -> Result<()> {
+        pd_func_caller!(
+            (*self.raw_subsystem).setLoopCallback,
+            self.raw_player,
+            None,
+            ptr::null_mut()
+        )?;
+        self.loop_callback = None;
+        Ok(())
+    }
+}
+
+impl SoundSource for SamplePlayer {
+    fn raw_source(&self) -> *mut crankstart_sys::SoundSource {
+        self.raw_player as *mut crankstart_sys::SoundSource
+    }
 }
 
-/// A loaded sound effect.
+/// A loaded sound effect, or audio data made at runtime.
+///
+/// For example, to make a short blip at startup:
+///
+/// ```ignore
+/// let frames: Vec<i16> = (0..2205)
+///     .map(|i| if (i / 50) % 2 == 0 { 8000 } else { -8000 })
+///     .collect();
+/// let blip = AudioSample::from_pcm16(&frames, false, 44100)?;
+/// player.set_sample(&blip)?;
+/// ```
 // Really a wrapper around an Rc clone of the internal structure; derive Clone so it's easy to get
 // another Rc reference.  We use Rc so we don't free the sample before we're done using it.
 #[derive(Clone, Debug)]
@@ -178,6 +254,8 @@ pub struct AudioSample {
 struct AudioSampleInner {
     raw_subsystem: *const crankstart_sys::playdate_sound_sample,
     raw_audio_sample: *mut crankstart_sys::AudioSample,
+    // Data we gave the sample, which it plays from rather than copying; freed after it.
+    data: Option<Vec<u8>>,
 }
 
 impl Drop for AudioSampleInner {
@@ -187,12 +265,18 @@ impl Drop for AudioSampleInner {
     }
 }
 
-// Not implemented: getData, newSampleBuffer, loadIntoSample, newSampleFromData -
-// only Sound::load_audio_sample for now.
 impl AudioSample {
     pub(crate) fn new(
         raw_subsystem: *const crankstart_sys::playdate_sound_sample,
         raw_audio_sample: *mut crankstart_sys::AudioSample,
+    ) -> Result<Self, Error> {
+        Self::new_with_data(raw_subsystem, raw_audio_sample, None)
+    }
+
+    fn new_with_data(
+        raw_subsystem: *const crankstart_sys::playdate_sound_sample,
+        raw_audio_sample: *mut crankstart_sys::AudioSample,
+        data: Option<Vec<u8>>,
     ) -> Result<Self, Error> {
         ensure!(
             !raw_subsystem.is_null(),
@@ -206,10 +290,161 @@ impl AudioSample {
             inner: Rc::new(AudioSampleInner {
                 raw_subsystem,
                 raw_audio_sample,
+                data,
             }),
         })
     }
 
+    /// Creates an empty sample with room for `byte_count` bytes, to load files into with
+    /// `load_into_sample`.
+    pub fn with_buffer(byte_count: usize) -> Result<Self> {
+        let raw_subsystem = Sound::get().raw_sample;
+        let raw_audio_sample = pd_func_caller!(
+            (*raw_subsystem).newSampleBuffer,
+            byte_count as ctypes::c_int
+        )?;
+        ensure!(
+            !raw_audio_sample.is_null(),
+            "Null returned from sample.newSampleBuffer"
+        );
+        Self::new(raw_subsystem, raw_audio_sample)
+    }
+
+    /// Creates a sample from 16-bit PCM frames, interleaved left and right if `stereo`.
+    pub fn from_pcm16(frames: &[i16], stereo: bool, sample_rate: u32) -> Result<Self> {
+        let format = if stereo {
+            SoundFormat::kSound16bitStereo
+        } else {
+            SoundFormat::kSound16bitMono
+        };
+        let data = frames
+            .iter()
+            .flat_map(|frame| frame.to_le_bytes())
+            .collect();
+        Self::from_vec(data, format, sample_rate)
+    }
+
+    /// Creates a sample from data in `format`.  8-bit PCM is signed, and 16-bit PCM is little
+    /// endian.  The data is copied, so it can be dropped afterwards.
+    pub fn from_data(data: &[u8], format: SoundFormat, sample_rate: u32) -> Result<Self> {
+        Self::from_vec(data.to_vec(), format, sample_rate)
+    }
+
+    pub(crate) fn from_vec(
+        mut data: Vec<u8>,
+        format: SoundFormat,
+        sample_rate: u32,
+    ) -> Result<Self> {
+        if let Some(bytes_per_frame) = bytes_per_frame(format) {
+            ensure!(
+                data.len() % bytes_per_frame == 0,
+                "Sample data of {} bytes isn't a whole number of {:?} frames",
+                data.len(),
+                format
+            );
+        }
+        let raw_subsystem = Sound::get().raw_sample;
+        let raw_audio_sample = pd_func_caller!(
+            (*raw_subsystem).newSampleFromData,
+            data.as_mut_ptr(),
+            format,
+            sample_rate,
+            data.len() as ctypes::c_int,
+            0
+        )?;
+        ensure!(
+            !raw_audio_sample.is_null(),
+            "Null returned from sample.newSampleFromData"
+        );
+        Self::new_with_data(raw_subsystem, raw_audio_sample, Some(data))
+    }
+
+    /// Loads an audio file into this sample, replacing what it held.  Like
+    /// `Sound::load_audio_sample`, the path can name the .wav file the compiled .pda came from.
+    pub fn load_into_sample(&self, sample_path: &str) -> Result<()> {
+        let sample_path_c = CString::new(sample_path).map_err(Error::msg)?;
+        let arg_ptr = sample_path_c.as_ptr() as *const ctypes::c_char;
+        let result = pd_func_caller!(
+            (*self.inner.raw_subsystem).loadIntoSample,
+            self.inner.raw_audio_sample,
+            arg_ptr
+        )?;
+        ensure!(result == 1, "Couldn't load '{}' into sample", sample_path);
+        Ok(())
+    }
+
+    fn get_raw_data(&self) -> Result<(*mut u8, SampleInfo)> {
+        let mut data = ptr::null_mut();
+        let mut format = SoundFormat::kSound16bitMono;
+        let mut sample_rate = 0;
+        let mut byte_length = 0;
+        pd_func_caller!(
+            (*self.inner.raw_subsystem).getData,
+            self.inner.raw_audio_sample,
+            &mut data,
+            &mut format,
+            &mut sample_rate,
+            &mut byte_length
+        )?;
+        Ok((
+            data,
+            SampleInfo {
+                format,
+                sample_rate,
+                byte_length,
+            },
+        ))
+    }
+
+    /// Returns the format, rate and size of the sample's data.
+    pub fn get_info(&self) -> Result<SampleInfo> {
+        Ok(self.get_raw_data()?.1)
+    }
+
+    /// Returns a copy of the sample's data, in the format given by `get_info`.
+    pub fn get_data(&self) -> Result<Vec<u8>> {
+        let (data, info) = self.get_raw_data()?;
+        if data.is_null() || info.byte_length == 0 {
+            return Ok(Vec::new());
+        }
+        Ok(unsafe { slice::from_raw_parts(data, info.byte_length as usize) }.to_vec())
+    }
+
+    /// Returns the sample's PCM frames as 16-bit values, interleaved left and right for stereo
+    /// samples.  ADPCM samples must be decompressed first.
+    pub fn get_frames(&self) -> Result<Vec<i16>> {
+        let info = self.get_info()?;
+        let data = self.get_data()?;
+        match info.format {
+            SoundFormat::kSound8bitMono | SoundFormat::kSound8bitStereo => Ok(data
+                .iter()
+                .map(|&byte| i16::from(byte as i8) << 8)
+                .collect()),
+            SoundFormat::kSound16bitMono | SoundFormat::kSound16bitStereo => Ok(data
+                .chunks_exact(2)
+                .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))
+                .collect()),
+            SoundFormat::kSoundADPCMMono | SoundFormat::kSoundADPCMStereo => Err(anyhow!(
+                "Can't read frames of an ADPCM sample; decompress it first"
+            )),
+        }
+    }
+
+    /// Converts an ADPCM sample to 16-bit PCM, e.g. so a `Synth` can play it.  Does nothing to
+    /// PCM samples.
+    pub fn decompress(&self) -> Result<()> {
+        let result = pd_func_caller!(
+            (*self.inner.raw_subsystem).decompress,
+            self.inner.raw_audio_sample
+        )?;
+        ensure!(result == 1, "sample.decompress failed");
+        Ok(())
+    }
+
+    pub(crate) fn raw_audio_sample(&self) -> *mut crankstart_sys::AudioSample {
+        self.inner.raw_audio_sample
+    }
+
     /// Returns the length of the sample, in seconds.
     pub fn get_length(&self) -> Result<f32> {
         pd_func_caller!(
@@ -218,3 +453,38 @@ impl AudioSample {
         )
     }
 }
+
+/// The format, rate and size of an `AudioSample`'s data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SampleInfo {
+    pub format: SoundFormat,
+    /// Frames per second.
+    pub sample_rate: u32,
+    pub byte_length: u32,
+}
+
+impl SampleInfo {
+    pub fn is_stereo(&self) -> bool {
+        matches!(
+            self.format,
+            SoundFormat::kSound8bitStereo
+                | SoundFormat::kSound16bitStereo
+                | SoundFormat::kSoundADPCMStereo
+        )
+    }
+
+    /// Returns the number of frames, or None for ADPCM, whose frames vary in size.
+    pub fn frame_count(&self) -> Option<u32> {
+        bytes_per_frame(self.format).map(|bytes| self.byte_length / bytes as u32)
+    }
+}
+
+/// Returns the size of a PCM frame in `format`, or None for ADPCM.
+fn bytes_per_frame(format: SoundFormat) -> Option<usize> {
+    match format {
+        SoundFormat::kSound8bitMono => Some(1),
+        SoundFormat::kSound8bitStereo | SoundFormat::kSound16bitMono => Some(2),
+        SoundFormat::kSound16bitStereo => Some(4),
+        SoundFormat::kSoundADPCMMono | SoundFormat::kSoundADPCMStereo => None,
+    }
+}
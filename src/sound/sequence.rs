@@ -0,0 +1,339 @@
+use super::{callback::SoundCallback, Instrument, Sound};
+use crate::{pd_func_caller, pd_func_caller_log};
+use crankstart_sys::ctypes;
+
+use alloc::vec::Vec;
+use anyhow::{anyhow, ensure, Error, Result};
+use core::{cell::RefCell, ops::Range, ptr};
+use cstr_core::CString;
+
+/// A note in a `Track`.  Steps are the sequence's unit of time; see `Sequence::set_tempo`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NoteEvent {
+    pub step: u32,
+    pub length: u32,
+    /// MIDI note number, where 60 is middle C.
+    pub note: f32,
+    /// Out of 1.
+    pub velocity: f32,
+}
+
+/// Tracks of notes played by instruments, such as a MIDI file.
+///
+/// ```ignore
+/// let music = Sequence::load_midi("music.mid")?;
+/// for index in 0..music.get_track_count()? {
+///     if let Some(track) = music.get_track(index)? {
+///         track.set_instrument(&instrument_for(index)?)?;
+///     }
+/// }
+/// music.set_loops(0..music.get_length()? as i32, 0)?;
+/// music.play()?;
+///
+/// // Later, for adaptive music:
+/// music.get_track(3)?.unwrap().set_muted(true)?;
+/// ```
+///
+/// Note: Make sure you hold on to a Sequence until it has played as much as you want, because
+/// dropping it will stop playback.
+#[derive(Debug)]
+pub struct Sequence {
+    raw_subsystem: *const crankstart_sys::playdate_sound_sequence,
+    raw_sequence: *mut crankstart_sys::SoundSequence,
+
+    // The instrument set on each track, held so they're not freed while the tracks play them.
+    instruments: RefCell<Vec<(*mut crankstart_sys::SequenceTrack, Instrument)>>,
+    // The finish callback given to the last play, held so it's not freed while it can be called.
+    finish_callback: RefCell<Option<SoundCallback>>,
+}
+
+impl Drop for Sequence {
+    fn drop(&mut self) {
+        // Use _log to leak rather than fail
+        pd_func_caller_log!((*self.raw_subsystem).freeSequence, self.raw_sequence);
+    }
+}
+
+// Not implemented: setTrackAtIndex (tracks are created with add_track, so the sequence owns
+// them all).
+impl Sequence {
+    /// Creates an empty sequence; add tracks with `add_track`.
+    pub fn new() -> Result<Self> {
+        let raw_subsystem = Sound::get().raw_sequence;
+        let raw_sequence = pd_func_caller!((*raw_subsystem).newSequence)?;
+        ensure!(
+            !raw_sequence.is_null(),
+            "Null returned from sequence.newSequence"
+        );
+        Ok(Self {
+            raw_subsystem,
+            raw_sequence,
+            instruments: RefCell::new(Vec::new()),
+            finish_callback: RefCell::new(None),
+        })
+    }
+
+    /// Loads a standard MIDI file.  Its tracks have no instruments, so set one on each track
+    /// that should be heard.
+    pub fn load_midi(path: &str) -> Result<Self> {
+        let sequence = Self::new()?;
+        let path_c = CString::new(path).map_err(Error::msg)?;
+        let arg_ptr = path_c.as_ptr() as *const ctypes::c_char;
+        let result = pd_func_caller!(
+            (*sequence.raw_subsystem).loadMIDIFile,
+            sequence.raw_sequence,
+            arg_ptr
+        )?;
+        ensure!(result == 1, "Couldn't load MIDI file '{}'", path);
+        Ok(sequence)
+    }
+
+    /// Starts playing from the current time.
+    pub fn play(&self) -> Result<()> {
+        pd_func_caller!(
+            (*self.raw_subsystem).play,
+            self.raw_sequence,
+            None,
+            ptr::null_mut()
+        )?;
+        self.finish_callback.replace(None);
+        Ok(())
+    }
+
+    /// Starts playing from the current time, calling `on_finish` when the sequence ends.
+    /// Like all sound callbacks, it's called at the start of the next update rather than
+    /// straight away.
+    pub fn play_with_callback(&self, on_finish: impl FnOnce() + 'static) -> Result<()> {
+        let callback = SoundCallback::once(on_finish);
+        pd_func_caller!(
+            (*self.raw_subsystem).play,
+            self.raw_sequence,
+            Some(SoundCallback::proc),
+            callback.userdata()
+        )?;
+        self.finish_callback.replace(Some(callback));
+        Ok(())
+    }
+
+    pub fn stop(&self) -> Result<()> {
+        pd_func_caller!((*self.raw_subsystem).stop, self.raw_sequence)
+    }
+
+    pub fn is_playing(&self) -> Result<bool> {
+        let result = pd_func_caller!((*self.raw_subsystem).isPlaying, self.raw_sequence)?;
+        Ok(result == 1)
+    }
+
+    /// Releases every note playing on every track.
+    pub fn all_notes_off(&self) -> Result<()> {
+        pd_func_caller!((*self.raw_subsystem).allNotesOff, self.raw_sequence)
+    }
+
+    /// Returns the current time, in samples since the start of the sequence.
+    pub fn get_time(&self) -> Result<u32> {
+        pd_func_caller!((*self.raw_subsystem).getTime, self.raw_sequence)
+    }
+
+    pub fn set_time(&self, time: u32) -> Result<()> {
+        pd_func_caller!((*self.raw_subsystem).setTime, self.raw_sequence, time)
+    }
+
+    /// Loops the steps in `steps` `loops` times, or forever if 0.
+    pub fn set_loops(&self, steps: Range<ctypes::c_int>, loops: ctypes::c_int) -> Result<()> {
+        pd_func_caller!(
+            (*self.raw_subsystem).setLoops,
+            self.raw_sequence,
+            steps.start,
+            steps.end,
+            loops
+        )
+    }
+
+    /// Returns the tempo, in steps per second.
+    pub fn get_tempo(&self) -> Result<f32> {
+        pd_func_caller!((*self.raw_subsystem).getTempo, self.raw_sequence)
+    }
+
+    /// Sets the tempo, in steps per second.
+    pub fn set_tempo(&self, steps_per_second: f32) -> Result<()> {
+        pd_func_caller!(
+            (*self.raw_subsystem).setTempo,
+            self.raw_sequence,
+            steps_per_second
+        )
+    }
+
+    /// Returns the length of the longest track, in steps.
+    pub fn get_length(&self) -> Result<u32> {
+        pd_func_caller!((*self.raw_subsystem).getLength, self.raw_sequence)
+    }
+
+    /// Returns the step being played and how many samples into it playback is.
+    pub fn get_current_step(&self) -> Result<(ctypes::c_int, ctypes::c_int)> {
+        let mut time_offset = 0;
+        let step = pd_func_caller!(
+            (*self.raw_subsystem).getCurrentStep,
+            self.raw_sequence,
+            &mut time_offset
+        )?;
+        Ok((step, time_offset))
+    }
+
+    /// Jumps to `step`, `time_offset` samples in.  If `play_notes` is true, notes that would
+    /// still be sounding at that point are started.
+    pub fn set_current_step(
+        &self,
+        step: ctypes::c_int,
+        time_offset: ctypes::c_int,
+        play_notes: bool,
+    ) -> Result<()> {
+        pd_func_caller!(
+            (*self.raw_subsystem).setCurrentStep,
+            self.raw_sequence,
+            step,
+            time_offset,
+            play_notes as ctypes::c_int
+        )
+    }
+
+    pub fn get_track_count(&self) -> Result<ctypes::c_int> {
+        pd_func_caller!((*self.raw_subsystem).getTrackCount, self.raw_sequence)
+    }
+
+    /// Adds an empty track to the end of the sequence.
+    pub fn add_track(&self) -> Result<Track<'_>> {
+        let raw_track = pd_func_caller!((*self.raw_subsystem).addTrack, self.raw_sequence)?;
+        ensure!(!raw_track.is_null(), "Null returned from sequence.addTrack");
+        Ok(Track::new(self, raw_track))
+    }
+
+    /// Returns the track at `index`, or None if there isn't one.  MIDI files can leave gaps.
+    pub fn get_track(&self, index: ctypes::c_int) -> Result<Option<Track<'_>>> {
+        let raw_track = pd_func_caller!(
+            (*self.raw_subsystem).getTrackAtIndex,
+            self.raw_sequence,
+            index as ctypes::c_uint
+        )?;
+        Ok((!raw_track.is_null()).then(|| Track::new(self, raw_track)))
+    }
+}
+
+/// One track of a `Sequence`, owned by the sequence.
+#[derive(Debug)]
+pub struct Track<'a> {
+    sequence: &'a Sequence,
+    raw_subsystem: *const crankstart_sys::playdate_sound_track,
+    raw_track: *mut crankstart_sys::SequenceTrack,
+}
+
+// Not implemented: control signals.
+impl<'a> Track<'a> {
+    fn new(sequence: &'a Sequence, raw_track: *mut crankstart_sys::SequenceTrack) -> Self {
+        Self {
+            sequence,
+            raw_subsystem: Sound::get().raw_track,
+            raw_track,
+        }
+    }
+
+    /// Sets the instrument that plays the track's notes.
+    pub fn set_instrument(&self, instrument: &Instrument) -> Result<()> {
+        pd_func_caller!(
+            (*self.raw_subsystem).setInstrument,
+            self.raw_track,
+            instrument.raw_instrument()
+        )?;
+        let mut instruments = self.sequence.instruments.borrow_mut();
+        instruments.retain(|(track, _)| *track != self.raw_track);
+        instruments.push((self.raw_track, instrument.clone()));
+        Ok(())
+    }
+
+    /// Returns the instrument set with `set_instrument`.
+    pub fn get_instrument(&self) -> Option<Instrument> {
+        self.sequence
+            .instruments
+            .borrow()
+            .iter()
+            .find(|(track, _)| *track == self.raw_track)
+            .map(|(_, instrument)| instrument.clone())
+    }
+
+    /// Adds a note at `step` lasting `length` steps.
+    pub fn add_note_event(&self, event: NoteEvent) -> Result<()> {
+        pd_func_caller!(
+            (*self.raw_subsystem).addNoteEvent,
+            self.raw_track,
+            event.step,
+            event.length,
+            event.note,
+            event.velocity
+        )
+    }
+
+    /// Removes the note `note` starting at `step`.
+    pub fn remove_note_event(&self, step: u32, note: f32) -> Result<()> {
+        pd_func_caller!(
+            (*self.raw_subsystem).removeNoteEvent,
+            self.raw_track,
+            step,
+            note
+        )
+    }
+
+    pub fn clear_notes(&self) -> Result<()> {
+        pd_func_caller!((*self.raw_subsystem).clearNotes, self.raw_track)
+    }
+
+    /// Returns every note in the track, in order.
+    pub fn notes(&self) -> Result<Vec<NoteEvent>> {
+        let mut notes = Vec::new();
+        loop {
+            let mut event = NoteEvent {
+                step: 0,
+                length: 0,
+                note: 0.0,
+                velocity: 0.0,
+            };
+            let found = pd_func_caller!(
+                (*self.raw_subsystem).getNoteAtIndex,
+                self.raw_track,
+                notes.len() as ctypes::c_int,
+                &mut event.step,
+                &mut event.length,
+                &mut event.note,
+                &mut event.velocity
+            )?;
+            if found == 0 {
+                return Ok(notes);
+            }
+            notes.push(event);
+        }
+    }
+
+    /// Returns the most notes the track plays at once, i.e. how many voices its instrument
+    /// needs.
+    pub fn get_polyphony(&self) -> Result<ctypes::c_int> {
+        pd_func_caller!((*self.raw_subsystem).getPolyphony, self.raw_track)
+    }
+
+    /// Returns the number of notes currently playing.
+    pub fn active_voice_count(&self) -> Result<ctypes::c_int> {
+        pd_func_caller!((*self.raw_subsystem).activeVoiceCount, self.raw_track)
+    }
+
+    /// Silences the track while the sequence keeps playing, e.g. to bring in a part of the
+    /// music as the game gets more intense.
+    pub fn set_muted(&self, muted: bool) -> Result<()> {
+        pd_func_caller!(
+            (*self.raw_subsystem).setMuted,
+            self.raw_track,
+            muted as ctypes::c_int
+        )
+    }
+
+    /// Returns the length of the track, in steps.
+    pub fn get_length(&self) -> Result<u32> {
+        pd_func_caller!((*self.raw_subsystem).getLength, self.raw_track)
+    }
+}
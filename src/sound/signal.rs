@@ -0,0 +1,262 @@
+//! Signals that modulate sound parameters over time.  `Lfo` and `Envelope` have their own
+//! modules.
+
+use super::Sound;
+use crate::{pd_func_caller, pd_func_caller_log};
+use crankstart_sys::ctypes;
+
+use alloc::{boxed::Box, rc::Rc};
+use anyhow::{anyhow, ensure, Error, Result};
+use core::{cell::UnsafeCell, fmt};
+
+/// A signal that can modulate a parameter of a synth, channel or effect.  Setters that take a
+/// modulator hold on to a handle to it, so it isn't freed while it's in use.
+pub trait Modulator: fmt::Debug {
+    /// Returns the signal to pass to the C API.
+    fn raw_signal_value(&self) -> *mut crankstart_sys::PDSynthSignalValue;
+
+    /// Returns another handle to the same signal.
+    fn clone_modulator(&self) -> Box<dyn Modulator>;
+}
+
+/// Returns the raw signal to pass for an optional modulator, and the handle to keep.
+pub(crate) fn modulator_parts(
+    modulator: Option<&dyn Modulator>,
+) -> (
+    *mut crankstart_sys::PDSynthSignalValue,
+    Option<Box<dyn Modulator>>,
+) {
+    match modulator {
+        Some(modulator) => (
+            modulator.raw_signal_value(),
+            Some(modulator.clone_modulator()),
+        ),
+        None => (core::ptr::null_mut(), None),
+    }
+}
+
+/// A signal, freed when the last handle is dropped.
+#[derive(Debug)]
+pub(crate) struct SignalInner<T> {
+    pub(crate) raw: *mut T,
+    free: Option<unsafe extern "C" fn(*mut T)>,
+}
+
+impl<T> SignalInner<T> {
+    pub(crate) fn new(raw: *mut T, free: Option<unsafe extern "C" fn(*mut T)>) -> Result<Rc<Self>> {
+        ensure!(!raw.is_null(), "Null pointer returned creating signal");
+        Ok(Rc::new(Self { raw, free }))
+    }
+
+    pub(crate) fn raw_signal_value(&self) -> *mut crankstart_sys::PDSynthSignalValue {
+        self.raw as *mut crankstart_sys::PDSynthSignalValue
+    }
+}
+
+impl<T> Drop for SignalInner<T> {
+    fn drop(&mut self) {
+        // Use _log to leak rather than fail
+        pd_func_caller_log!(self.free, self.raw);
+    }
+}
+
+/// A signal that steps or ramps between values at given steps of a `Sequence`, e.g. to
+/// automate a filter cutoff along with the music.
+///
+/// ```ignore
+/// let sweep = ControlSignal::new()?;
+/// sweep.add_event(0, 0.0, false)?;
+/// sweep.add_event(16, 1.0, true)?;
+/// filter.set_frequency_modulator(Some(&sweep))?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct ControlSignal {
+    inner: Rc<SignalInner<crankstart_sys::ControlSignal>>,
+}
+
+impl ControlSignal {
+    pub fn new() -> Result<Self> {
+        let subsystem = Self::subsystem();
+        let raw = pd_func_caller!((*subsystem).newSignal)?;
+        Ok(Self {
+            inner: SignalInner::new(raw, unsafe { (*subsystem).freeSignal })?,
+        })
+    }
+
+    fn subsystem() -> *const crankstart_sys::playdate_control_signal {
+        Sound::get().raw_control_signal
+    }
+
+    pub fn clear_events(&self) -> Result<()> {
+        pd_func_caller!((*Self::subsystem()).clearEvents, self.inner.raw)
+    }
+
+    /// Sets the signal to `value` at `step`.  If `interpolate` is true, the signal ramps to
+    /// it from the previous event rather than jumping.
+    pub fn add_event(&self, step: ctypes::c_int, value: f32, interpolate: bool) -> Result<()> {
+        pd_func_caller!(
+            (*Self::subsystem()).addEvent,
+            self.inner.raw,
+            step,
+            value,
+            interpolate as ctypes::c_int
+        )
+    }
+
+    pub fn remove_event(&self, step: ctypes::c_int) -> Result<()> {
+        pd_func_caller!((*Self::subsystem()).removeEvent, self.inner.raw, step)
+    }
+
+    /// Returns the MIDI controller number of a signal loaded from a MIDI file.
+    pub fn get_midi_controller_number(&self) -> Result<ctypes::c_int> {
+        pd_func_caller!((*Self::subsystem()).getMIDIControllerNumber, self.inner.raw)
+    }
+}
+
+impl Modulator for ControlSignal {
+    fn raw_signal_value(&self) -> *mut crankstart_sys::PDSynthSignalValue {
+        self.inner.raw_signal_value()
+    }
+
+    fn clone_modulator(&self) -> Box<dyn Modulator> {
+        Box::new(self.clone())
+    }
+}
+
+/// Computes a signal in Rust.  Its methods are called from the audio thread, so they should
+/// be quick and must not call back into the Playdate API.
+pub trait SignalSource: Send + 'static {
+    /// Called each render cycle with the number of frames about to be rendered, returning the
+    /// signal's value.  As with the SDK's `signalStepFunc`, the source can lower `frames` to
+    /// change value partway through the cycle, setting `interframe_value` to the value until
+    /// then.
+    fn step(&mut self, frames: &mut ctypes::c_int, interframe_value: &mut f32) -> f32;
+
+    /// Called when a note starts on the synth or instrument the signal modulates, with its
+    /// length in seconds if known.
+    fn note_on(&mut self, _note: f32, _velocity: f32, _length: Option<f32>) {}
+
+    /// Called when the note is released, or stopped if `stopped` is true, `offset` frames into
+    /// the current cycle.
+    fn note_off(&mut self, _stopped: bool, _offset: ctypes::c_int) {}
+}
+
+/// A `SignalSource` that can be used as a modulator like the built-in signals.
+///
+/// The signal owns the source, so share any settings to change while it's playing through
+/// atomics, e.g. in an `Arc`.
+pub struct CustomSignal<S: SignalSource> {
+    inner: Rc<SignalInner<crankstart_sys::PDSynthSignal>>,
+    // Declared after `inner` so the signal calling it is freed first.  The Rc keeps its
+    // address, which the signal holds as userdata, from moving.
+    source: Rc<UnsafeCell<S>>,
+}
+
+impl<S: SignalSource> CustomSignal<S> {
+    pub fn new(source: S) -> Result<Self> {
+        let subsystem = Self::subsystem();
+        let source = Rc::new(UnsafeCell::new(source));
+        let raw = pd_func_caller!(
+            (*subsystem).newSignal,
+            Some(signal_step::<S>),
+            Some(signal_note_on::<S>),
+            Some(signal_note_off::<S>),
+            None,
+            source.get() as *mut ctypes::c_void
+        )?;
+        Ok(Self {
+            inner: SignalInner::new(raw, unsafe { (*subsystem).freeSignal })?,
+            source,
+        })
+    }
+
+    fn subsystem() -> *const crankstart_sys::playdate_sound_signal {
+        Sound::get().raw_signal
+    }
+
+    /// Returns the signal's current value, after scaling and offsetting.
+    pub fn get_value(&self) -> Result<f32> {
+        pd_func_caller!((*Self::subsystem()).getValue, self.inner.raw)
+    }
+
+    /// Multiplies the source's values by `scale`.
+    pub fn set_value_scale(&self, scale: f32) -> Result<()> {
+        pd_func_caller!((*Self::subsystem()).setValueScale, self.inner.raw, scale)
+    }
+
+    /// Adds `offset` to the source's values, after scaling.
+    pub fn set_value_offset(&self, offset: f32) -> Result<()> {
+        pd_func_caller!((*Self::subsystem()).setValueOffset, self.inner.raw, offset)
+    }
+}
+
+impl<S: SignalSource> Clone for CustomSignal<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            source: self.source.clone(),
+        }
+    }
+}
+
+impl<S: SignalSource> fmt::Debug for CustomSignal<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CustomSignal")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl<S: SignalSource> Modulator for CustomSignal<S> {
+    fn raw_signal_value(&self) -> *mut crankstart_sys::PDSynthSignalValue {
+        self.inner.raw_signal_value()
+    }
+
+    fn clone_modulator(&self) -> Box<dyn Modulator> {
+        Box::new(self.clone())
+    }
+}
+
+extern "C" fn signal_step<S: SignalSource>(
+    userdata: *mut ctypes::c_void,
+    frames: *mut ctypes::c_int,
+    interframe_value: *mut f32,
+) -> f32 {
+    if userdata.is_null() || frames.is_null() {
+        return 0.0;
+    }
+    let source = unsafe { &mut *(userdata as *mut S) };
+    let mut unused = 0.0;
+    let interframe_value = if interframe_value.is_null() {
+        &mut unused
+    } else {
+        unsafe { &mut *interframe_value }
+    };
+    source.step(unsafe { &mut *frames }, interframe_value)
+}
+
+extern "C" fn signal_note_on<S: SignalSource>(
+    userdata: *mut ctypes::c_void,
+    note: crankstart_sys::MIDINote,
+    velocity: f32,
+    length: f32,
+) {
+    if userdata.is_null() {
+        return;
+    }
+    let source = unsafe { &mut *(userdata as *mut S) };
+    let length = if length < 0.0 { None } else { Some(length) };
+    source.note_on(note, velocity, length);
+}
+
+extern "C" fn signal_note_off<S: SignalSource>(
+    userdata: *mut ctypes::c_void,
+    stopped: ctypes::c_int,
+    offset: ctypes::c_int,
+) {
+    if userdata.is_null() {
+        return;
+    }
+    let source = unsafe { &mut *(userdata as *mut S) };
+    source.note_off(stopped != 0, offset);
+}
@@ -0,0 +1,8 @@
+//! Things that make sound: players, synths, instruments and delay line taps.
+
+/// A sound source that can be added to a `Channel`.  Sources not added to a channel play
+/// through the default channel.
+pub trait SoundSource {
+    /// Returns the source to pass to the C API.
+    fn raw_source(&self) -> *mut crankstart_sys::SoundSource;
+}
@@ -0,0 +1,349 @@
+use super::{
+    signal::{modulator_parts, Modulator},
+    source::SoundSource,
+    AudioSample, Sound,
+};
+use crate::{pd_func_caller, pd_func_caller_log};
+use crankstart_sys::ctypes;
+
+use alloc::{boxed::Box, vec::Vec};
+use anyhow::{anyhow, ensure, Error, Result};
+use core::ops::Range;
+
+pub use crankstart_sys::SoundWaveform;
+
+/// The volume envelope applied to each note: how long it takes to reach full volume, how
+/// long to fall to the sustain level, and how long to fade out after the note ends.  Times are
+/// in seconds; `sustain` is out of 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Adsr {
+    pub attack: f32,
+    pub decay: f32,
+    pub sustain: f32,
+    pub release: f32,
+}
+
+impl Default for Adsr {
+    /// Full volume for as long as the note is held, with no fade in or out.
+    fn default() -> Self {
+        Self {
+            attack: 0.0,
+            decay: 0.0,
+            sustain: 1.0,
+            release: 0.0,
+        }
+    }
+}
+
+/// A synthesizer voice.  It plays through the default channel unless added to another one.
+///
+/// ```ignore
+/// let synth = Synth::new()?;
+/// synth.set_waveform(SoundWaveform::kWaveformSquare)?;
+/// synth.set_adsr(&Adsr { attack: 0.01, decay: 0.1, sustain: 0.5, release: 0.2 })?;
+/// synth.play_midi_note(60.0, 1.0, Some(0.25), 0)?;
+/// ```
+///
+/// Note: Make sure you hold on to a Synth until it has played as much as you want, because
+/// dropping it will stop playback.
+#[derive(Debug)]
+pub struct Synth {
+    raw_subsystem: *const crankstart_sys::playdate_sound_synth,
+    raw_synth: *mut crankstart_sys::PDSynth,
+
+    // The sample or wavetable being played, and the modulators in use, held so they're not
+    // freed while the synth uses them.
+    sample: Option<AudioSample>,
+    frequency_modulator: Option<Box<dyn Modulator>>,
+    amplitude_modulator: Option<Box<dyn Modulator>>,
+    parameter_modulators: Vec<(ctypes::c_int, Box<dyn Modulator>)>,
+}
+
+impl Drop for Synth {
+    fn drop(&mut self) {
+        // Use _log to leak rather than fail
+        pd_func_caller_log!((*self.raw_subsystem).freeSynth, self.raw_synth);
+    }
+}
+
+// Not implemented: setGenerator and getEnvelope.
+impl Synth {
+    /// Creates a synth playing a square wave.
+    pub fn new() -> Result<Self> {
+        let raw_subsystem = Sound::get().raw_synth;
+        let raw_synth = pd_func_caller!((*raw_subsystem).newSynth)?;
+        Self::from_raw(raw_subsystem, raw_synth)
+    }
+
+    /// Creates a synth that plays `sample`, which must be uncompressed PCM rather than ADPCM.
+    /// If given, the frames in `sustain` loop while the note is held.
+    pub fn new_with_sample(sample: &AudioSample, sustain: Option<Range<u32>>) -> Result<Self> {
+        let mut synth = Self::new()?;
+        synth.set_sample(sample, sustain)?;
+        Ok(synth)
+    }
+
+    fn from_raw(
+        raw_subsystem: *const crankstart_sys::playdate_sound_synth,
+        raw_synth: *mut crankstart_sys::PDSynth,
+    ) -> Result<Self> {
+        ensure!(
+            !raw_subsystem.is_null(),
+            "Null pointer given as subsystem to Synth::new"
+        );
+        ensure!(!raw_synth.is_null(), "Null returned from synth.newSynth");
+        Ok(Self {
+            raw_subsystem,
+            raw_synth,
+            sample: None,
+            frequency_modulator: None,
+            amplitude_modulator: None,
+            parameter_modulators: Vec::new(),
+        })
+    }
+
+    pub(crate) fn raw_synth(&self) -> *mut crankstart_sys::PDSynth {
+        self.raw_synth
+    }
+
+    /// Returns a new synth with the same settings, sample and modulators.
+    pub fn copy(&self) -> Result<Self> {
+        let raw_synth = pd_func_caller!((*self.raw_subsystem).copy, self.raw_synth)?;
+        let mut synth = Self::from_raw(self.raw_subsystem, raw_synth)?;
+        synth.sample = self.sample.clone();
+        synth.frequency_modulator = self
+            .frequency_modulator
+            .as_ref()
+            .map(|modulator| modulator.clone_modulator());
+        synth.amplitude_modulator = self
+            .amplitude_modulator
+            .as_ref()
+            .map(|modulator| modulator.clone_modulator());
+        synth.parameter_modulators = self
+            .parameter_modulators
+            .iter()
+            .map(|(parameter, modulator)| (*parameter, modulator.clone_modulator()))
+            .collect();
+        Ok(synth)
+    }
+
+    pub fn set_waveform(&self, waveform: SoundWaveform) -> Result<()> {
+        pd_func_caller!((*self.raw_subsystem).setWaveform, self.raw_synth, waveform)
+    }
+
+    /// Plays `sample` instead of a waveform; see `new_with_sample`.
+    pub fn set_sample(&mut self, sample: &AudioSample, sustain: Option<Range<u32>>) -> Result<()> {
+        let sustain = sustain.unwrap_or(0..0);
+        pd_func_caller!(
+            (*self.raw_subsystem).setSample,
+            self.raw_synth,
+            sample.raw_audio_sample(),
+            sustain.start,
+            sustain.end
+        )?;
+        self.sample = Some(sample.clone());
+        Ok(())
+    }
+
+    /// Plays a wavetable: `sample` holds `columns` by `rows` waveforms, each 2^`log2_size`
+    /// frames long, and parameters 1 and 2 move through the columns and rows.  The sample
+    /// must be 16-bit mono, and `rows` must be a power of two.
+    pub fn set_wavetable(
+        &mut self,
+        sample: &AudioSample,
+        log2_size: ctypes::c_int,
+        columns: ctypes::c_int,
+        rows: ctypes::c_int,
+    ) -> Result<()> {
+        let result = pd_func_caller!(
+            (*self.raw_subsystem).setWavetable,
+            self.raw_synth,
+            sample.raw_audio_sample(),
+            log2_size,
+            columns,
+            rows
+        )?;
+        ensure!(result == 1, "synth.setWavetable failed");
+        self.sample = Some(sample.clone());
+        Ok(())
+    }
+
+    pub fn set_adsr(&self, adsr: &Adsr) -> Result<()> {
+        self.set_attack_time(adsr.attack)?;
+        self.set_decay_time(adsr.decay)?;
+        self.set_sustain_level(adsr.sustain)?;
+        self.set_release_time(adsr.release)
+    }
+
+    pub fn set_attack_time(&self, seconds: f32) -> Result<()> {
+        pd_func_caller!((*self.raw_subsystem).setAttackTime, self.raw_synth, seconds)
+    }
+
+    pub fn set_decay_time(&self, seconds: f32) -> Result<()> {
+        pd_func_caller!((*self.raw_subsystem).setDecayTime, self.raw_synth, seconds)
+    }
+
+    /// Sets the volume held after the decay, out of 1.
+    pub fn set_sustain_level(&self, level: f32) -> Result<()> {
+        pd_func_caller!((*self.raw_subsystem).setSustainLevel, self.raw_synth, level)
+    }
+
+    pub fn set_release_time(&self, seconds: f32) -> Result<()> {
+        pd_func_caller!(
+            (*self.raw_subsystem).setReleaseTime,
+            self.raw_synth,
+            seconds
+        )
+    }
+
+    /// Shifts the pitch of every note by `half_steps`.
+    pub fn set_transpose(&self, half_steps: f32) -> Result<()> {
+        pd_func_caller!(
+            (*self.raw_subsystem).setTranspose,
+            self.raw_synth,
+            half_steps
+        )
+    }
+
+    /// Modulates the pitch, or stops modulating it with None.
+    pub fn set_frequency_modulator(&mut self, modulator: Option<&dyn Modulator>) -> Result<()> {
+        let (raw_signal, modulator) = modulator_parts(modulator);
+        pd_func_caller!(
+            (*self.raw_subsystem).setFrequencyModulator,
+            self.raw_synth,
+            raw_signal
+        )?;
+        self.frequency_modulator = modulator;
+        Ok(())
+    }
+
+    /// Modulates the volume, or stops modulating it with None.
+    pub fn set_amplitude_modulator(&mut self, modulator: Option<&dyn Modulator>) -> Result<()> {
+        let (raw_signal, modulator) = modulator_parts(modulator);
+        pd_func_caller!(
+            (*self.raw_subsystem).setAmplitudeModulator,
+            self.raw_synth,
+            raw_signal
+        )?;
+        self.amplitude_modulator = modulator;
+        Ok(())
+    }
+
+    /// Returns the number of parameters the synth's waveform has; the Pocket Operator
+    /// waveforms and wavetables have two.
+    pub fn get_parameter_count(&self) -> Result<ctypes::c_int> {
+        pd_func_caller!((*self.raw_subsystem).getParameterCount, self.raw_synth)
+    }
+
+    pub fn set_parameter(&self, parameter: ctypes::c_int, value: f32) -> Result<()> {
+        let result = pd_func_caller!(
+            (*self.raw_subsystem).setParameter,
+            self.raw_synth,
+            parameter,
+            value
+        )?;
+        ensure!(result != 0, "Synth has no parameter {}", parameter);
+        Ok(())
+    }
+
+    /// Modulates a parameter, or stops modulating it with None.
+    pub fn set_parameter_modulator(
+        &mut self,
+        parameter: ctypes::c_int,
+        modulator: Option<&dyn Modulator>,
+    ) -> Result<()> {
+        let (raw_signal, modulator) = modulator_parts(modulator);
+        pd_func_caller!(
+            (*self.raw_subsystem).setParameterModulator,
+            self.raw_synth,
+            parameter,
+            raw_signal
+        )?;
+        self.parameter_modulators
+            .retain(|(held, _)| *held != parameter);
+        if let Some(modulator) = modulator {
+            self.parameter_modulators.push((parameter, modulator));
+        }
+        Ok(())
+    }
+
+    /// Plays a note of `frequency` Hz at `velocity`, out of 1.  The note ends after `length`
+    /// seconds, or when `note_off` is called if None.  `when` is the sound engine time to
+    /// start at (see `Sound::get_current_time`), or 0 for now.
+    pub fn play_note(
+        &self,
+        frequency: f32,
+        velocity: f32,
+        length: Option<f32>,
+        when: u32,
+    ) -> Result<()> {
+        pd_func_caller!(
+            (*self.raw_subsystem).playNote,
+            self.raw_synth,
+            frequency,
+            velocity,
+            length.unwrap_or(-1.0),
+            when
+        )
+    }
+
+    /// Plays a MIDI note number, where 60 is middle C, like `play_note`.  Fractional notes
+    /// are allowed.
+    pub fn play_midi_note(
+        &self,
+        note: f32,
+        velocity: f32,
+        length: Option<f32>,
+        when: u32,
+    ) -> Result<()> {
+        pd_func_caller!(
+            (*self.raw_subsystem).playMIDINote,
+            self.raw_synth,
+            note,
+            velocity,
+            length.unwrap_or(-1.0),
+            when
+        )
+    }
+
+    /// Releases the playing note at sound engine time `when`, or 0 for now; it fades out
+    /// over the release time.
+    pub fn note_off(&self, when: u32) -> Result<()> {
+        pd_func_caller!((*self.raw_subsystem).noteOff, self.raw_synth, when)
+    }
+
+    /// Stops the synth immediately, without a release.
+    pub fn stop(&self) -> Result<()> {
+        pd_func_caller!((*self.raw_subsystem).stop, self.raw_synth)
+    }
+
+    /// Returns whether a note is playing, including its release.
+    pub fn is_playing(&self) -> Result<bool> {
+        let result = pd_func_caller!((*self.raw_subsystem).isPlaying, self.raw_synth)?;
+        Ok(result == 1)
+    }
+
+    /// Gets the current volume of the left and right audio channels, out of 1.
+    pub fn get_volume(&self) -> Result<(f32, f32)> {
+        let mut left = 0.0;
+        let mut right = 0.0;
+        pd_func_caller!(
+            (*self.raw_subsystem).getVolume,
+            self.raw_synth,
+            &mut left,
+            &mut right,
+        )?;
+        Ok((left, right))
+    }
+
+    /// Sets the volume of the left and right audio channels, out of 1.
+    pub fn set_volume(&self, left: f32, right: f32) -> Result<()> {
+        pd_func_caller!((*self.raw_subsystem).setVolume, self.raw_synth, left, right)
+    }
+}
+
+impl SoundSource for Synth {
+    fn raw_source(&self) -> *mut crankstart_sys::SoundSource {
+        self.raw_synth as *mut crankstart_sys::SoundSource
+    }
+}
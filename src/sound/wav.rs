@@ -0,0 +1,267 @@
+//! A decoder for creating audio samples at runtime from WAV files, e.g. files downloaded into
+//! the data folder, or data embedded with `include_bytes!`.  Compiled .pda audio should still
+//! be loaded with `Sound::load_audio_sample`.
+//!
+//! ```ignore
+//! let recorded = Sound::get().load_wav("recordings/take1.wav")?;
+//! let beep = AudioSample::from_wav(include_bytes!("beep.wav"))?;
+//! ```
+
+use super::{AudioSample, Sound, SoundFormat};
+use crate::file::FileSystem;
+
+use alloc::vec::Vec;
+use anyhow::{anyhow, bail, ensure, Error, Result};
+
+const FORMAT_PCM: u16 = 1;
+const FORMAT_IMA_ADPCM: u16 = 0x11;
+const FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+impl AudioSample {
+    /// Creates a sample from a WAV file holding 8 or 16-bit PCM, or IMA ADPCM, in mono or
+    /// stereo.  ADPCM is decoded to 16-bit PCM.
+    pub fn from_wav(data: &[u8]) -> Result<AudioSample> {
+        ensure!(
+            data.len() >= 12 && &data[0..4] == b"RIFF" && &data[8..12] == b"WAVE",
+            "Not a WAV file"
+        );
+
+        let mut fmt = None;
+        let mut samples = None;
+        let mut pos = 12;
+        while pos + 8 <= data.len() {
+            let id = &data[pos..pos + 4];
+            let len = le_u32(&data[pos + 4..]) as usize;
+            let start = pos + 8;
+            // Some writers leave the data chunk's length unset, or too long, when streaming.
+            let end = start.saturating_add(len).min(data.len());
+            match id {
+                b"fmt " => fmt = Some(WavFormat::parse(&data[start..end])?),
+                b"data" => samples = Some(&data[start..end]),
+                _ => {}
+            }
+            // Chunks are padded to an even length.
+            pos = start.saturating_add(len).saturating_add(len & 1);
+        }
+        let fmt = fmt.ok_or_else(|| anyhow!("WAV file has no fmt chunk"))?;
+        let samples = samples.ok_or_else(|| anyhow!("WAV file has no data chunk"))?;
+
+        let stereo = match fmt.channels {
+            1 => false,
+            2 => true,
+            channels => bail!("Unsupported WAV channel count {}", channels),
+        };
+        let (format, data) = match (fmt.format, fmt.bits_per_sample) {
+            (FORMAT_PCM, 8) => {
+                // WAV's 8-bit samples are unsigned, and the Playdate's are signed.
+                let format = if stereo {
+                    SoundFormat::kSound8bitStereo
+                } else {
+                    SoundFormat::kSound8bitMono
+                };
+                let frame_bytes = if stereo { 2 } else { 1 };
+                let len = samples.len() - samples.len() % frame_bytes;
+                (
+                    format,
+                    samples[..len].iter().map(|byte| byte ^ 0x80).collect(),
+                )
+            }
+            (FORMAT_PCM, 16) => {
+                let format = if stereo {
+                    SoundFormat::kSound16bitStereo
+                } else {
+                    SoundFormat::kSound16bitMono
+                };
+                let frame_bytes = if stereo { 4 } else { 2 };
+                let len = samples.len() - samples.len() % frame_bytes;
+                (format, samples[..len].to_vec())
+            }
+            (FORMAT_IMA_ADPCM, 4) => {
+                let format = if stereo {
+                    SoundFormat::kSound16bitStereo
+                } else {
+                    SoundFormat::kSound16bitMono
+                };
+                (format, decode_ima_adpcm(samples, &fmt)?)
+            }
+            (format, bits) => bail!(
+                "Unsupported WAV format {:#x} with {} bits per sample",
+                format,
+                bits
+            ),
+        };
+        AudioSample::from_vec(data, format, fmt.sample_rate)
+    }
+}
+
+impl Sound {
+    /// Loads a WAV file from the data folder, or the game's pdx; see `AudioSample::from_wav`.
+    pub fn load_wav(&self, path: &str) -> Result<AudioSample> {
+        let data = FileSystem::get().read_file(path)?;
+        AudioSample::from_wav(&data).map_err(|err| anyhow!("Couldn't load '{}': {}", path, err))
+    }
+}
+
+fn le_u16(bytes: &[u8]) -> u16 {
+    u16::from_le_bytes([bytes[0], bytes[1]])
+}
+
+fn le_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+struct WavFormat {
+    format: u16,
+    channels: u16,
+    sample_rate: u32,
+    block_align: u16,
+    bits_per_sample: u16,
+    // Only given for ADPCM.
+    samples_per_block: Option<u16>,
+}
+
+impl WavFormat {
+    fn parse(chunk: &[u8]) -> Result<Self> {
+        ensure!(chunk.len() >= 16, "WAV fmt chunk is truncated");
+        let mut format = le_u16(chunk);
+        let extra = if chunk.len() >= 18 {
+            le_u16(&chunk[16..]) as usize
+        } else {
+            0
+        };
+        // The extensible format gives the real format in the first two bytes of its GUID.
+        if format == FORMAT_EXTENSIBLE {
+            ensure!(
+                extra >= 22 && chunk.len() >= 26,
+                "WAV extensible fmt chunk is truncated"
+            );
+            format = le_u16(&chunk[24..]);
+        }
+        let samples_per_block = if format == FORMAT_IMA_ADPCM && extra >= 2 && chunk.len() >= 20 {
+            Some(le_u16(&chunk[18..]))
+        } else {
+            None
+        };
+        Ok(Self {
+            format,
+            channels: le_u16(&chunk[2..]),
+            sample_rate: le_u32(&chunk[4..]),
+            block_align: le_u16(&chunk[12..]),
+            bits_per_sample: le_u16(&chunk[14..]),
+            samples_per_block,
+        })
+    }
+}
+
+const ADPCM_STEPS: [i32; 89] = [
+    7, 8, 9, 10, 11, 12, 13, 14, 16, 17, 19, 21, 23, 25, 28, 31, 34, 37, 41, 45, 50, 55, 60, 66,
+    73, 80, 88, 97, 107, 118, 130, 143, 157, 173, 190, 209, 230, 253, 279, 307, 337, 371, 408, 449,
+    494, 544, 598, 658, 724, 796, 876, 963, 1060, 1166, 1282, 1411, 1552, 1707, 1878, 2066, 2272,
+    2499, 2749, 3024, 3327, 3660, 4026, 4428, 4871, 5358, 5894, 6484, 7132, 7845, 8630, 9493,
+    10442, 11487, 12635, 13899, 15289, 16818, 18500, 20350, 22385, 24623, 27086, 29794, 32767,
+];
+
+const ADPCM_INDEX_CHANGES: [i32; 8] = [-1, -1, -1, -1, 2, 4, 6, 8];
+
+struct AdpcmChannel {
+    predictor: i32,
+    index: i32,
+}
+
+impl AdpcmChannel {
+    fn decode(&mut self, nibble: u8) -> i16 {
+        let step = ADPCM_STEPS[self.index as usize];
+        let mut diff = step >> 3;
+        if nibble & 4 != 0 {
+            diff += step;
+        }
+        if nibble & 2 != 0 {
+            diff += step >> 1;
+        }
+        if nibble & 1 != 0 {
+            diff += step >> 2;
+        }
+        if nibble & 8 != 0 {
+            self.predictor -= diff;
+        } else {
+            self.predictor += diff;
+        }
+        self.predictor = self.predictor.clamp(i16::MIN as i32, i16::MAX as i32);
+        self.index = (self.index + ADPCM_INDEX_CHANGES[(nibble & 7) as usize]).clamp(0, 88);
+        self.predictor as i16
+    }
+}
+
+/// Decodes IMA ADPCM blocks to little endian 16-bit PCM, interleaved for stereo.  Each block
+/// starts with a header per channel giving its first sample and step index; after that, mono
+/// blocks hold two samples per byte, low nibble first, and stereo blocks alternate four bytes
+/// of left samples with four bytes of right.
+fn decode_ima_adpcm(data: &[u8], fmt: &WavFormat) -> Result<Vec<u8>> {
+    let channels = fmt.channels as usize;
+    let block_align = fmt.block_align as usize;
+    ensure!(
+        block_align > 4 * channels,
+        "Invalid IMA ADPCM block size {}",
+        block_align
+    );
+    // A block can't hold more samples than its header and nibbles encode, whatever the fmt
+    // chunk claims.
+    let block_samples = (block_align - 4 * channels) * 2 / channels + 1;
+    let samples_per_block = fmt.samples_per_block.map_or(block_samples, |samples| {
+        (samples as usize).min(block_samples)
+    });
+
+    // Every input byte decodes to at most two 16-bit samples.
+    let mut output = Vec::with_capacity(data.len().saturating_mul(4));
+    let mut frame = [0i16; 2];
+    for block in data.chunks(block_align) {
+        if block.len() <= 4 * channels {
+            break;
+        }
+        let mut states = [
+            AdpcmChannel {
+                predictor: 0,
+                index: 0,
+            },
+            AdpcmChannel {
+                predictor: 0,
+                index: 0,
+            },
+        ];
+        for (channel, state) in states.iter_mut().enumerate().take(channels) {
+            let header = &block[channel * 4..];
+            state.predictor = le_u16(header) as i16 as i32;
+            state.index = (header[2] as i32).clamp(0, 88);
+            output.extend_from_slice(&(state.predictor as i16).to_le_bytes());
+        }
+
+        // Decode a group of up to 8 samples per channel at a time, 4 bytes from each.
+        let body = &block[4 * channels..];
+        let mut decoded = 1;
+        for group in body.chunks(4 * channels) {
+            // A truncated final group can hold fewer bytes for the right channel than the
+            // left, and frames need a sample from both.
+            let channel_bytes = (0..channels)
+                .map(|channel| group.len().saturating_sub(channel * 4).min(4))
+                .min()
+                .unwrap_or(0);
+            let group_samples = (channel_bytes * 2).min(samples_per_block.saturating_sub(decoded));
+            for sample in 0..group_samples {
+                for (channel, state) in states.iter_mut().enumerate().take(channels) {
+                    let byte = group[channel * 4 + sample / 2];
+                    let nibble = if sample % 2 == 0 {
+                        byte & 0xf
+                    } else {
+                        byte >> 4
+                    };
+                    frame[channel] = state.decode(nibble);
+                }
+                for value in &frame[..channels] {
+                    output.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+            decoded += group_samples;
+        }
+    }
+    Ok(output)
+}
@@ -27,6 +27,9 @@ use {
     hashbrown::HashMap,
 };
 
+pub mod collision;
+pub use collision::{CollisionKind, StaticCollision};
+
 pub use crankstart_sys::SpriteCollisionResponseType;
 
 // Currently no font:getHeight in C API.
@@ -264,6 +267,14 @@ impl SpriteInner {
         )
     }
 
+    pub fn set_ignores_draw_offset(&self, ignores: bool) -> Result<(), Error> {
+        pd_func_caller!(
+            (*self.playdate_sprite).setIgnoresDrawOffset,
+            self.raw_sprite,
+            ignores as i32
+        )
+    }
+
     pub fn move_to(&mut self, x: f32, y: f32) -> Result<(), Error> {
         pd_func_caller!((*self.playdate_sprite).moveTo, self.raw_sprite, x, y)
     }
@@ -473,6 +484,15 @@ impl Sprite {
             .set_opaque(opaque)
     }
 
+    /// Makes the sprite draw at its position on screen, ignoring `Graphics::set_draw_offset`;
+    /// for HUD elements that shouldn't scroll with the world.
+    pub fn set_ignores_draw_offset(&self, ignores: bool) -> Result<(), Error> {
+        self.inner
+            .try_borrow()
+            .map_err(Error::msg)?
+            .set_ignores_draw_offset(ignores)
+    }
+
     pub fn get_position(&self) -> Result<(f32, f32), Error> {
         self.inner.try_borrow().map_err(Error::msg)?.get_position()
     }
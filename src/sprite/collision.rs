@@ -0,0 +1,331 @@
+//! Static level collision built from a tile grid.
+//!
+//! Rather than one sprite per solid tile, neighboring tiles of the same kind are merged into as
+//! few rectangles as possible and each rectangle gets one invisible, collision-only sprite.
+//!
+//! ```ignore
+//! let collision = StaticCollision::build(
+//!     map_width,
+//!     map_height,
+//!     size2(16, 16),
+//!     point2(0, 0),
+//!     CollisionTags::default(),
+//!     |x, y| match tilemap.get_tile(x, y)?.index {
+//!         0..=31 => Some(CollisionKind::Solid),
+//!         32 => Some(CollisionKind::OneWay),
+//!         _ => None,
+//!     },
+//! )?;
+//! ```
+
+use {
+    super::{Sprite, SpriteManager},
+    crate::{
+        geometry::{ScreenPoint, ScreenRect, ScreenSize},
+        graphics::PDRect,
+    },
+    alloc::{vec, vec::Vec},
+    anyhow::Error,
+};
+
+/// How a tile blocks movement.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CollisionKind {
+    /// Blocks from every side.
+    Solid,
+    /// A platform that can be jumped up through and stood on.  Only merged along rows, since
+    /// each tile's top edge is a platform.
+    OneWay,
+    /// A slope whose floor rises from the bottom left corner of the tile to the top right.
+    /// The sprite covers the whole tile; the game works out the floor height.  Never merged.
+    SlopeUpRight,
+    /// A slope whose floor rises from the bottom right corner to the top left.
+    SlopeUpLeft,
+}
+
+/// The sprite tags given to each kind of collision sprite, so collision handlers can tell
+/// them apart with `Sprite::get_tag`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CollisionTags {
+    pub solid: u8,
+    pub one_way: u8,
+    pub slope_up_right: u8,
+    pub slope_up_left: u8,
+}
+
+impl Default for CollisionTags {
+    fn default() -> Self {
+        Self {
+            solid: 1,
+            one_way: 2,
+            slope_up_right: 3,
+            slope_up_left: 4,
+        }
+    }
+}
+
+impl CollisionTags {
+    pub fn tag(&self, kind: CollisionKind) -> u8 {
+        match kind {
+            CollisionKind::Solid => self.solid,
+            CollisionKind::OneWay => self.one_way,
+            CollisionKind::SlopeUpRight => self.slope_up_right,
+            CollisionKind::SlopeUpLeft => self.slope_up_left,
+        }
+    }
+
+    /// Returns the kind of collision sprite with `tag`, or None if it isn't one.
+    pub fn kind(&self, tag: u8) -> Option<CollisionKind> {
+        [
+            CollisionKind::Solid,
+            CollisionKind::OneWay,
+            CollisionKind::SlopeUpRight,
+            CollisionKind::SlopeUpLeft,
+        ]
+        .iter()
+        .copied()
+        .find(|&kind| self.tag(kind) == tag)
+    }
+}
+
+/// A rectangle of cells, in cell units.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GridRect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// Covers the cells of a `width` by `height` grid for which `solid` returns true with
+/// non-overlapping rectangles, by greedy meshing: each rectangle is grown as far right as
+/// possible, then as far down.  This isn't always the fewest rectangles possible, but it's
+/// close and fast.
+pub fn merge_cells<F>(width: usize, height: usize, solid: F) -> Vec<GridRect>
+where
+    F: FnMut(usize, usize) -> bool,
+{
+    merge(width, height, solid, true)
+}
+
+fn merge<F>(width: usize, height: usize, mut solid: F, grow_down: bool) -> Vec<GridRect>
+where
+    F: FnMut(usize, usize) -> bool,
+{
+    let mut open = vec![false; width * height];
+    for y in 0..height {
+        for x in 0..width {
+            open[y * width + x] = solid(x, y);
+        }
+    }
+
+    let mut rects = Vec::new();
+    for y in 0..height {
+        let mut x = 0;
+        while x < width {
+            if !open[y * width + x] {
+                x += 1;
+                continue;
+            }
+            let mut rect_width = 1;
+            while x + rect_width < width && open[y * width + x + rect_width] {
+                rect_width += 1;
+            }
+            let mut rect_height = 1;
+            if grow_down {
+                while y + rect_height < height {
+                    let row = (y + rect_height) * width;
+                    if !open[row + x..row + x + rect_width].iter().all(|&cell| cell) {
+                        break;
+                    }
+                    rect_height += 1;
+                }
+            }
+            for row in y..y + rect_height {
+                open[row * width + x..row * width + x + rect_width]
+                    .iter_mut()
+                    .for_each(|cell| *cell = false);
+            }
+            rects.push(GridRect {
+                x,
+                y,
+                width: rect_width,
+                height: rect_height,
+            });
+            x += rect_width;
+        }
+    }
+    rects
+}
+
+/// Invisible sprites registered with the `SpriteManager` for a level's static collision.
+/// They're removed from the display list and freed when this is dropped.
+#[derive(Debug)]
+pub struct StaticCollision {
+    sprites: Vec<Sprite>,
+    tags: CollisionTags,
+}
+
+impl StaticCollision {
+    /// Builds collision for a `width` by `height` grid of `tile_size` tiles whose top left
+    /// corner is at `origin`.  `classify` gives the kind of each cell, or None for open space.
+    pub fn build<F>(
+        width: usize,
+        height: usize,
+        tile_size: ScreenSize,
+        origin: ScreenPoint,
+        tags: CollisionTags,
+        mut classify: F,
+    ) -> Result<Self, Error>
+    where
+        F: FnMut(usize, usize) -> Option<CollisionKind>,
+    {
+        let mut kinds = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                kinds.push(classify(x, y));
+            }
+        }
+        let kind_at = |kind: CollisionKind| {
+            let kinds = &kinds;
+            move |x: usize, y: usize| kinds[y * width + x] == Some(kind)
+        };
+
+        let mut shapes = Vec::new();
+        for rect in merge(width, height, kind_at(CollisionKind::Solid), true) {
+            shapes.push((rect, CollisionKind::Solid));
+        }
+        for rect in merge(width, height, kind_at(CollisionKind::OneWay), false) {
+            shapes.push((rect, CollisionKind::OneWay));
+        }
+        for kind in [CollisionKind::SlopeUpRight, CollisionKind::SlopeUpLeft] {
+            let mut is_kind = kind_at(kind);
+            for y in 0..height {
+                for x in (0..width).filter(|&x| is_kind(x, y)) {
+                    let rect = GridRect {
+                        x,
+                        y,
+                        width: 1,
+                        height: 1,
+                    };
+                    shapes.push((rect, kind));
+                }
+            }
+        }
+
+        let sprite_manager = SpriteManager::get_mut();
+        let mut sprites = Vec::with_capacity(shapes.len());
+        for (rect, kind) in shapes {
+            let bounds = ScreenRect::new(
+                ScreenPoint::new(
+                    origin.x + rect.x as i32 * tile_size.width,
+                    origin.y + rect.y as i32 * tile_size.height,
+                ),
+                ScreenSize::new(
+                    rect.width as i32 * tile_size.width,
+                    rect.height as i32 * tile_size.height,
+                ),
+            );
+            sprites.push(add_collision_sprite(
+                sprite_manager,
+                bounds,
+                tags.tag(kind),
+            )?);
+        }
+        Ok(Self { sprites, tags })
+    }
+
+    /// Builds collision with every cell either solid or open.
+    pub fn from_solid<F>(
+        width: usize,
+        height: usize,
+        tile_size: ScreenSize,
+        origin: ScreenPoint,
+        mut solid: F,
+    ) -> Result<Self, Error>
+    where
+        F: FnMut(usize, usize) -> bool,
+    {
+        Self::build(
+            width,
+            height,
+            tile_size,
+            origin,
+            CollisionTags::default(),
+            |x, y| solid(x, y).then_some(CollisionKind::Solid),
+        )
+    }
+
+    /// Builds collision from rectangles in screen coordinates that are all of `kind`, e.g.
+    /// collision objects drawn in a level editor.
+    pub fn from_rects<I>(rects: I, kind: CollisionKind, tags: CollisionTags) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = ScreenRect>,
+    {
+        let sprite_manager = SpriteManager::get_mut();
+        let mut sprites = Vec::new();
+        for bounds in rects {
+            sprites.push(add_collision_sprite(
+                sprite_manager,
+                bounds,
+                tags.tag(kind),
+            )?);
+        }
+        Ok(Self { sprites, tags })
+    }
+
+    pub fn sprites(&self) -> &[Sprite] {
+        &self.sprites
+    }
+
+    pub fn len(&self) -> usize {
+        self.sprites.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sprites.is_empty()
+    }
+
+    pub fn get_tags(&self) -> CollisionTags {
+        self.tags
+    }
+
+    /// Returns the kind of a collision sprite from its tag, or None for other sprites.
+    pub fn kind_of(&self, sprite: &Sprite) -> Result<Option<CollisionKind>, Error> {
+        Ok(self.tags.kind(sprite.get_tag()?))
+    }
+}
+
+impl Drop for StaticCollision {
+    fn drop(&mut self) {
+        let sprite_manager = SpriteManager::get_mut();
+        for sprite in &self.sprites {
+            let _ = sprite_manager.remove_sprite(sprite);
+        }
+    }
+}
+
+/// Adds an invisible sprite covering `bounds` that only takes part in collisions.
+pub(crate) fn add_collision_sprite(
+    sprite_manager: &mut SpriteManager,
+    bounds: ScreenRect,
+    tag: u8,
+) -> Result<Sprite, Error> {
+    let mut sprite = sprite_manager.new_sprite()?;
+    sprite.set_bounds(&PDRect {
+        x: bounds.origin.x as f32,
+        y: bounds.origin.y as f32,
+        width: bounds.size.width as f32,
+        height: bounds.size.height as f32,
+    })?;
+    sprite.set_collide_rect(&PDRect {
+        x: 0.0,
+        y: 0.0,
+        width: bounds.size.width as f32,
+        height: bounds.size.height as f32,
+    })?;
+    sprite.set_visible(false)?;
+    sprite.set_tag(tag)?;
+    sprite_manager.add_sprite(&sprite)?;
+    Ok(sprite)
+}
@@ -10,12 +10,18 @@ use crankstart_sys::ctypes::{c_char, c_int};
 pub use crankstart_sys::PDButtons;
 use crankstart_sys::{PDDateTime, PDLanguage, PDMenuItem, PDPeripherals};
 use {
-    crate::pd_func_caller, anyhow::Error, core::ptr, crankstart_sys::ctypes::c_void,
+    crate::{graphics::Bitmap, pd_func_caller},
+    anyhow::Error,
+    core::ptr,
+    crankstart_sys::ctypes::c_void,
     cstr_core::CString,
 };
 
 static mut SYSTEM: System = System(ptr::null_mut());
 
+// setMenuImage doesn't copy the bitmap; it's kept here until replaced.
+static mut MENU_IMAGE: Option<Bitmap> = None;
+
 #[derive(Clone, Debug)]
 pub struct System(*const crankstart_sys::playdate_sys);
 
@@ -61,6 +67,20 @@ impl System {
         }
     }
 
+    /// Sets the image shown beside the menu when the game is paused, e.g. a snapshot from
+    /// `Graphics::copy_framebuffer`.  The image should be 400x240; `x_offset` slides it left to
+    /// keep the interesting part visible.  Pass None to remove the image.
+    pub fn set_menu_image(&self, image: Option<&Bitmap>, x_offset: i32) -> Result<(), Error> {
+        let raw_bitmap = image
+            .map(|image| image.inner.borrow().raw_bitmap)
+            .unwrap_or(ptr::null_mut());
+        pd_func_caller!((*self.0).setMenuImage, raw_bitmap, x_offset)?;
+        unsafe {
+            MENU_IMAGE = image.cloned();
+        }
+        Ok(())
+    }
+
     /// Adds a option to the menu. The callback is called when the option is selected.
     pub fn add_menu_item(&self, title: &str, callback: Box<dyn Fn()>) -> Result<MenuItem, Error> {
         let c_text = CString::new(title).map_err(|e| anyhow!("CString::new: {}", e))?;